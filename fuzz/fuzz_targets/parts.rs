@@ -0,0 +1,59 @@
+//! Parse arbitrary bytes as a `multipart/byteranges` body.
+//!
+//! The first byte gives the length of the boundary, which is taken from the following bytes;
+//! the rest is the body.
+//! Neither parser may panic, in either mode,
+//! and where strict [Parts] succeeds, [StreamingParts] must yield the same parts.
+#![no_main]
+
+use std::io::Read;
+
+use byteranges::response::{Bytes, PartDesc, Parts};
+use byteranges::streaming::StreamingParts;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&len, rest)) = data.split_first() else {
+        return;
+    };
+    let len = (len as usize).min(rest.len());
+    let (boundary, body) = rest.split_at(len);
+    let part_desc = || PartDesc::Multi {
+        boundary: [b"--", boundary].concat(),
+    };
+
+    let body_bytes = Bytes::copy_from_slice(body);
+    let parts: Result<Vec<_>, _> = Parts::new(part_desc(), body_bytes.clone()).collect();
+    for _ in Parts::new(part_desc(), body_bytes).set_lenient(true) {}
+
+    let mut streaming = StreamingParts::new(part_desc(), body);
+    let mut streamed = Vec::default();
+    let mut streaming_ok = true;
+    loop {
+        match streaming.next_part() {
+            Ok(Some(mut part)) => {
+                let offset_len = part.offset_len();
+                let mut buf = Vec::default();
+                if part.read_to_end(&mut buf).is_err() {
+                    streaming_ok = false;
+                    break;
+                }
+                streamed.push((offset_len, buf));
+            }
+            Ok(None) => break,
+            Err(_) => {
+                streaming_ok = false;
+                break;
+            }
+        }
+    }
+
+    if let Ok(parts) = parts {
+        assert!(streaming_ok);
+        assert_eq!(parts.len(), streamed.len());
+        for (part, (offset_len, data)) in parts.iter().zip(streamed.iter()) {
+            assert_eq!(part.offset_len(), *offset_len);
+            assert_eq!(part.data(), &data[..]);
+        }
+    }
+});
@@ -0,0 +1,22 @@
+//! Parse arbitrary strings as a `Range` header, and resolve and normalize the result.
+#![no_main]
+
+use byteranges::request::RangeHeader;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: (&str, u16, u16)| {
+    let (s, complete_length, gap) = input;
+    let complete_length = complete_length as usize;
+    let Ok(header) = RangeHeader::parse(s) else {
+        return;
+    };
+    if let Ok(resolved) = header.resolve(complete_length) {
+        for (offset, len) in resolved {
+            assert!(offset + len <= complete_length);
+            // only an empty representation can produce an empty range
+            assert!(len > 0 || complete_length == 0);
+        }
+    }
+    header.normalize(gap as u64, Some(complete_length));
+    header.normalize(gap as u64, None);
+});
@@ -0,0 +1,58 @@
+//! Interpret arbitrary status codes, headers and bodies as a response.
+//!
+//! Nothing may panic, whether or not the response makes sense.
+#![no_main]
+
+use std::io::Read;
+
+use byteranges::response::{Bytes, MaybePartialResponse};
+use libfuzzer_sys::fuzz_target;
+
+/// Header names which the response's interpretation depends on.
+const NAMES: [&str; 4] = ["Content-Type", "Content-Range", "ETag", "Last-Modified"];
+
+struct FuzzResponse {
+    status: u16,
+    headers: Vec<(u8, String)>,
+    body: Vec<u8>,
+}
+
+impl MaybePartialResponse for FuzzResponse {
+    type Error = std::convert::Infallible;
+
+    fn status_code(&self) -> u16 {
+        self.status
+    }
+
+    fn header_values(&self, name: &str) -> Vec<&str> {
+        self.headers
+            .iter()
+            .filter(|(n, _)| NAMES[usize::from(*n) % NAMES.len()].eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+            .collect()
+    }
+
+    fn body(self) -> Result<Bytes, Self::Error> {
+        Ok(self.body.into())
+    }
+}
+
+fuzz_target!(
+    |input: (bool, u16, Vec<(u8, String)>, Vec<u8>)| {
+        let (partial, status, headers, body) = input;
+        let response = FuzzResponse {
+            // most of the interesting behaviour is for 206 responses
+            status: if partial { 206 } else { status },
+            headers,
+            body,
+        };
+        let Ok(mut sparse) = response.sparse_body() else {
+            return;
+        };
+        // don't allocate for absurd reported lengths
+        if sparse.len() <= 1 << 20 {
+            let mut buf = Vec::default();
+            let _ = sparse.read_to_end(&mut buf);
+        }
+    }
+);
@@ -0,0 +1,48 @@
+//! Serialise parts of an arbitrary file as a `multipart/byteranges` body,
+//! then parse it back into a [SparseBody] and check it against the file.
+#![no_main]
+
+use std::io::Read;
+
+use byteranges::multipart::MultipartBuilder;
+use byteranges::response::{Bytes, PartDesc, Parts, SparseBody};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: (Vec<u8>, Vec<(u16, u16)>)| {
+    let (file, spans) = input;
+    if file.is_empty() || spans.is_empty() {
+        return;
+    }
+    let file = Bytes::from(file);
+    let spans: Vec<_> = spans
+        .into_iter()
+        .map(|(offset, len)| {
+            let offset = offset as usize % file.len();
+            let len = 1 + len as usize % (file.len() - offset);
+            (offset, len)
+        })
+        .collect();
+    let body = MultipartBuilder::new("application/octet-stream")
+        .complete_length(file.len())
+        .extend(spans.iter().map(|&(o, l)| (o, file.slice(o..o + l))))
+        .build()
+        .unwrap();
+
+    let part_desc = PartDesc::Multi {
+        boundary: format!("--{}", body.boundary()).into_bytes(),
+    };
+    let parts: Vec<_> = Parts::new(part_desc, body.to_bytes())
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(parts.len(), spans.len());
+
+    let mut sparse = SparseBody::new();
+    sparse.extend_parts(parts).unwrap();
+    let mut buf = Vec::default();
+    sparse.read_to_end(&mut buf).unwrap();
+    assert_eq!(buf.len(), file.len());
+    for (idx, (actual, expected)) in buf.iter().zip(file.iter()).enumerate() {
+        let held = spans.iter().any(|&(o, l)| (o..o + l).contains(&idx));
+        assert_eq!(*actual, if held { *expected } else { 0 });
+    }
+});
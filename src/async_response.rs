@@ -0,0 +1,161 @@
+use std::{future::Future, time::SystemTime};
+
+use httpdate::parse_http_date;
+
+use crate::request::{EntityTag, IfRange, RangeHeader, Validator};
+use crate::response::{
+    matches_if_range, part_description, single_value, unsatisfied_length, validator, Bytes,
+    PartDesc, PartialHeaderParseError, Parts, Source, SparseBody, SparseBodyError, CONTENT_RANGE,
+    CONTENT_TYPE, ETAG, LAST_MODIFIED,
+};
+
+/// Async counterpart to [MaybePartialResponse](crate::response::MaybePartialResponse),
+/// for responses whose body must be awaited.
+///
+/// Implemented for [reqwest::Response](https://docs.rs/reqwest/latest/reqwest/struct.Response.html)
+/// behind the `reqwest` feature flag.
+pub trait AsyncMaybePartialResponse: Sized + Send {
+    /// Error from reading the response body.
+    type Error: std::error::Error + Send + Sync + 'static;
+
+    fn status_code(&self) -> u16;
+
+    /// See [MaybePartialResponse::header_values](crate::response::MaybePartialResponse::header_values).
+    fn header_values(&self, name: &str) -> Vec<&str>;
+
+    /// Value of the response's `Content-Type` header if present, and not given conflicting values.
+    fn content_type_str(&self) -> Option<&str> {
+        single_value(CONTENT_TYPE, self.header_values(CONTENT_TYPE)).ok()?
+    }
+
+    /// Value of the response's `Content-Range` header if present, and not given conflicting values.
+    fn content_range_str(&self) -> Option<&str> {
+        single_value(CONTENT_RANGE, self.header_values(CONTENT_RANGE)).ok()?
+    }
+
+    /// Value of the response's `ETag` header if present, and not given conflicting values.
+    fn etag_str(&self) -> Option<&str> {
+        single_value(ETAG, self.header_values(ETAG)).ok()?
+    }
+
+    /// Value of the response's `Last-Modified` header if present, and not given conflicting values.
+    fn last_modified_str(&self) -> Option<&str> {
+        single_value(LAST_MODIFIED, self.header_values(LAST_MODIFIED)).ok()?
+    }
+
+    /// The bytes of the response body.
+    fn body(self) -> impl Future<Output = Result<Bytes, Self::Error>> + Send;
+
+    /// The response's entity tag, if it has a valid one.
+    fn etag(&self) -> Option<EntityTag> {
+        self.etag_str()?.parse().ok()
+    }
+
+    /// The response's `Last-Modified` date, if it has a valid one.
+    fn last_modified(&self) -> Option<SystemTime> {
+        parse_http_date(self.last_modified_str()?).ok()
+    }
+
+    /// See [MaybePartialResponse::validator](crate::response::MaybePartialResponse::validator).
+    fn validator(&self) -> Option<Validator> {
+        validator(self.etag(), self.last_modified())
+    }
+
+    /// See [MaybePartialResponse::matches_if_range](crate::response::MaybePartialResponse::matches_if_range).
+    fn matches_if_range(&self, if_range: &IfRange) -> bool {
+        matches_if_range(
+            self.status_code(),
+            self.etag(),
+            self.last_modified(),
+            if_range,
+        )
+    }
+
+    /// If the response is a 206 Partial, a description of what type based on the headers.
+    fn part_description(&self) -> Result<PartDesc, PartialHeaderParseError> {
+        part_description(
+            self.status_code(),
+            single_value(CONTENT_TYPE, self.header_values(CONTENT_TYPE))?,
+            single_value(CONTENT_RANGE, self.header_values(CONTENT_RANGE))?,
+        )
+    }
+
+    /// If the response is a 206 Partial, an iterator over its [ResponsePart](crate::response::ResponsePart)s.
+    fn parts(
+        self,
+    ) -> impl Future<Output = Result<Parts, PartialHeaderParseError<Self::Error>>> + Send {
+        async {
+            let part_desc = self.part_description().map_err(|e| e.widen())?;
+            let body = self
+                .body()
+                .await
+                .map_err(PartialHeaderParseError::BodyRead)?;
+            Ok(Parts::new(part_desc, body))
+        }
+    }
+
+    /// Representation of the whole requested file, with [Read](std::io::Read)/[Seek](std::io::Seek).
+    ///
+    /// See [MaybePartialResponse::sparse_body](crate::response::MaybePartialResponse::sparse_body).
+    fn sparse_body(
+        self,
+    ) -> impl Future<Output = Result<SparseBody, SparseBodyError<Self::Error>>> + Send {
+        async {
+            let mut body = SparseBody::new();
+            body.insert_response_async(self).await?;
+            Ok(body)
+        }
+    }
+
+    /// See [MaybePartialResponse::sparse_body_if_range](crate::response::MaybePartialResponse::sparse_body_if_range).
+    fn sparse_body_if_range(
+        self,
+        if_range: &IfRange,
+    ) -> impl Future<Output = Result<SparseBody, SparseBodyError<Self::Error>>> + Send {
+        let matches = self.matches_if_range(if_range);
+        let expected = if_range.validator().clone();
+        async move {
+            if !matches {
+                return Err(SparseBodyError::ValidatorMismatch {
+                    expected,
+                    found: self.validator(),
+                });
+            }
+            self.sparse_body().await
+        }
+    }
+}
+
+impl SparseBody {
+    /// Async counterpart to [SparseBody::insert_response].
+    pub async fn insert_response_async<R: AsyncMaybePartialResponse>(
+        &mut self,
+        response: R,
+    ) -> Result<(), SparseBodyError<R::Error>> {
+        let source = Source::new(response.etag(), response.last_modified());
+        let part_desc = match response.status_code() {
+            200 => None,
+            _ => Some(response.part_description().map_err(|e| e.widen())?),
+        };
+        let body = response.body().await.map_err(SparseBodyError::Body)?;
+        self.insert_body(source, part_desc, body)
+            .map_err(|e| e.widen())
+    }
+}
+
+/// Async counterpart to [retry_clamped](crate::response::retry_clamped).
+pub async fn retry_clamped<'a, R, E, F, Fut>(range: RangeHeader<'a>, mut send: F) -> Result<R, E>
+where
+    R: AsyncMaybePartialResponse,
+    F: FnMut(RangeHeader<'a>) -> Fut,
+    Fut: Future<Output = Result<R, E>>,
+{
+    let response = send(range.clone()).await?;
+    if response.status_code() != 416 {
+        return Ok(response);
+    }
+    match unsatisfied_length(response.content_range_str()).map(|l| range.clamp(l)) {
+        Some(Ok(clamped)) if clamped != range => send(clamped).await,
+        _ => Ok(response),
+    }
+}
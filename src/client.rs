@@ -0,0 +1,258 @@
+use std::{error::Error, sync::Mutex};
+
+use crate::multipart::MultipartError;
+use crate::remote::RangeTransport;
+use crate::request::{IfRange, RangeHeader};
+use crate::response::{Bytes, MaybePartialResponse, RangeResponse, Representation};
+
+#[cfg(feature = "http")]
+pub use crate::impls::http_impl::HttpClient;
+
+/// An HTTP client which can send range requests.
+///
+/// Implemented for [reqwest::blocking::Client](https://docs.rs/reqwest/latest/reqwest/blocking/struct.Client.html)
+/// and [HttpClient] behind the relevant feature flags, and for [MockClient].
+pub trait RangeClient {
+    /// What to request, e.g. a URL.
+    type Request;
+    type Response: MaybePartialResponse;
+    /// Error from sending a request, e.g. if the connection failed.
+    type Error: Error + Send + Sync + 'static;
+
+    /// Send a `GET` request with the given `Range` header (unless it is empty),
+    /// and `If-Range` header if given.
+    fn send(
+        &self,
+        request: &Self::Request,
+        range: &RangeHeader,
+        if_range: Option<&IfRange>,
+    ) -> Result<Self::Response, Self::Error>;
+
+    /// The length of the representation if it can be found cheaply, e.g. from the `Content-Length` of a `HEAD` request.
+    ///
+    /// By default [None].
+    fn content_length(&self, request: &Self::Request) -> Result<Option<usize>, Self::Error> {
+        let _ = request;
+        Ok(None)
+    }
+}
+
+/// A [RangeClient] with a fixed request, to be used as the [RangeTransport] of a
+/// [RemoteFile](crate::remote::RemoteFile).
+#[derive(Debug, Clone)]
+pub struct ClientTransport<C: RangeClient> {
+    pub client: C,
+    pub request: C::Request,
+}
+
+impl<C: RangeClient> ClientTransport<C> {
+    pub fn new(client: C, request: C::Request) -> Self {
+        Self { client, request }
+    }
+}
+
+impl<C: RangeClient> RangeTransport for ClientTransport<C> {
+    type Response = C::Response;
+    type Error = C::Error;
+
+    fn get(
+        &mut self,
+        range: &RangeHeader,
+        if_range: Option<&IfRange>,
+    ) -> Result<Self::Response, C::Error> {
+        self.client.send(&self.request, range, if_range)
+    }
+
+    fn content_length(&mut self) -> Result<Option<usize>, C::Error> {
+        self.client.content_length(&self.request)
+    }
+}
+
+/// In-memory [RangeClient] which serves a [Representation] from a buffer,
+/// as a server following [RFC 9110 §14](https://www.rfc-editor.org/rfc/rfc9110#section-14) would.
+///
+/// Satisfiable ranges get a 206 response, which is `multipart/byteranges` if there is more than one;
+/// a `Range` with no satisfiable ranges gets a 416 with the complete length,
+/// and a request without a `Range`, or whose `If-Range` does not match, gets the whole representation.
+/// The request itself is ignored.
+///
+/// ```rust
+/// # use byteranges::client::{MockClient, RangeClient};
+/// # use byteranges::response::{Bytes, MaybePartialResponse};
+/// let client = MockClient::new(Bytes::from_static(b"0123456789"));
+/// let response = client.send(&(), &(2..5).into(), None).unwrap();
+/// assert_eq!(response.status_code(), 206);
+/// assert_eq!(response.content_range_str(), Some("bytes 2-4/10"));
+/// ```
+#[derive(Debug)]
+pub struct MockClient {
+    representation: Representation,
+    head: bool,
+    ignore_range: bool,
+    /// `Range` header values of the requests received, empty if there was none.
+    requests: Mutex<Vec<String>>,
+}
+
+impl MockClient {
+    pub fn new(data: Bytes) -> Self {
+        Representation::new(data).into()
+    }
+
+    /// The representation being served.
+    pub fn representation(&self) -> &Representation {
+        &self.representation
+    }
+
+    /// Change the representation, e.g. to simulate it changing between requests.
+    pub fn representation_mut(&mut self) -> &mut Representation {
+        &mut self.representation
+    }
+
+    /// Whether [RangeClient::content_length] reports the length, as a `HEAD` request would (default true).
+    pub fn set_head(&mut self, head: bool) -> &mut Self {
+        self.head = head;
+        self
+    }
+
+    /// Whether to ignore `Range` headers and always return the whole representation (default false),
+    /// as a server without range support would.
+    pub fn set_ignore_range(&mut self, ignore_range: bool) -> &mut Self {
+        self.ignore_range = ignore_range;
+        self
+    }
+
+    /// `Range` header values of the requests received so far, empty if there was none.
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().expect("lock poisoned").clone()
+    }
+}
+
+impl From<Representation> for MockClient {
+    fn from(representation: Representation) -> Self {
+        Self {
+            representation,
+            head: true,
+            ignore_range: false,
+            requests: Mutex::default(),
+        }
+    }
+}
+
+impl RangeClient for MockClient {
+    type Request = ();
+    type Response = RangeResponse;
+    type Error = MultipartError;
+
+    fn send(
+        &self,
+        _request: &(),
+        range: &RangeHeader,
+        if_range: Option<&IfRange>,
+    ) -> Result<RangeResponse, MultipartError> {
+        let range_str = if range.is_empty() {
+            String::default()
+        } else {
+            range.to_string()
+        };
+        self.requests.lock().expect("lock poisoned").push(range_str);
+        if self.ignore_range {
+            self.representation.respond(&RangeHeader::default(), None)
+        } else {
+            self.representation.respond(range, if_range)
+        }
+    }
+
+    fn content_length(&self, _request: &()) -> Result<Option<usize>, MultipartError> {
+        Ok(self.head.then_some(self.representation.data().len()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request::EntityTag;
+    use crate::response::SparseBodyError;
+    use crate::test_impl::{lorem, read_text};
+    use std::io::Read;
+    use std::time::SystemTime;
+
+    fn client() -> MockClient {
+        lorem().into()
+    }
+
+    #[test]
+    fn single_range() {
+        let text = read_text();
+        let response = client().send(&(), &(50..101).into(), None).unwrap();
+        let parts: Vec<_> = response.parts().unwrap().collect();
+        assert_eq!(parts.len(), 1);
+        let part = parts[0].as_ref().unwrap();
+        assert_eq!(part.offset_len(), Some((50, 51)));
+        assert_eq!(part.data(), &text[50..101]);
+    }
+
+    #[test]
+    fn multiple_ranges() {
+        let text = read_text();
+        let range = RangeHeader::parse("bytes=0-9,-10,5000-").unwrap();
+        let mut body = client()
+            .send(&(), &range, None)
+            .unwrap()
+            .sparse_body()
+            .unwrap();
+        assert_eq!(body.populated(), &[(0, 10), (4047, 10)]);
+        let mut buf = Vec::default();
+        body.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf[4047..], text[4047..]);
+    }
+
+    #[test]
+    fn unsatisfiable() {
+        let response = client().send(&(), &(5000..).into(), None).unwrap();
+        assert_eq!(response.status(), 416);
+        assert_eq!(
+            response
+                .part_description()
+                .unwrap_err()
+                .unsatisfied_length(),
+            Some(4057)
+        );
+    }
+
+    #[test]
+    fn if_range() {
+        let client = client();
+        let same = IfRange::new(EntityTag::strong("1").unwrap()).unwrap();
+        let changed = IfRange::new(EntityTag::strong("2").unwrap()).unwrap();
+        let range = RangeHeader::from(0..10);
+        assert_eq!(client.send(&(), &range, Some(&same)).unwrap().status(), 206);
+        let response = client.send(&(), &range, Some(&changed)).unwrap();
+        assert_eq!(response.status(), 200);
+        assert!(matches!(
+            response.sparse_body_if_range(&changed),
+            Err(SparseBodyError::ValidatorMismatch { .. })
+        ));
+        // dates are not comparable with the entity tag
+        let date = IfRange::new(SystemTime::UNIX_EPOCH).unwrap();
+        assert_eq!(client.send(&(), &range, Some(&date)).unwrap().status(), 200);
+        assert_eq!(client.requests(), ["bytes=0-9"; 3]);
+    }
+
+    #[test]
+    fn no_range() {
+        let mut client = client();
+        assert_eq!(
+            client
+                .send(&(), &RangeHeader::default(), None)
+                .unwrap()
+                .status(),
+            200
+        );
+        client.set_ignore_range(true);
+        assert_eq!(
+            client.send(&(), &(0..10).into(), None).unwrap().status(),
+            200
+        );
+        assert_eq!(client.requests(), ["", "bytes=0-9"]);
+    }
+}
@@ -0,0 +1,76 @@
+use std::io::Read;
+
+pub use attohttpc;
+use attohttpc::{Response, Session};
+
+use crate::client::RangeClient;
+use crate::request::{IfRange, RangeHeader, IF_RANGE, RANGE};
+
+impl crate::response::MaybePartialResponse for Response {
+    type Error = attohttpc::Error;
+
+    fn status_code(&self) -> u16 {
+        self.status().as_u16()
+    }
+
+    fn header_values(&self, name: &str) -> Vec<&str> {
+        self.headers()
+            .get_all(name)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .collect()
+    }
+
+    fn body(self) -> Result<bytes::Bytes, attohttpc::Error> {
+        Ok(self.bytes()?.into())
+    }
+
+    fn body_reader<'a>(self) -> Result<Box<dyn Read + 'a>, attohttpc::Error>
+    where
+        Self: 'a,
+    {
+        Ok(Box::new(self))
+    }
+}
+
+impl RangeClient for Session {
+    type Request = String;
+    type Response = Response;
+    type Error = attohttpc::Error;
+
+    fn send(
+        &self,
+        request: &String,
+        range: &RangeHeader,
+        if_range: Option<&IfRange>,
+    ) -> Result<Response, attohttpc::Error> {
+        let mut builder = self.get(request);
+        if !range.is_empty() {
+            builder = builder.header(RANGE, range.to_value());
+        }
+        if let Some(if_range) = if_range {
+            builder = builder.header(IF_RANGE, if_range.to_value());
+        }
+        builder.send()
+    }
+
+    /// Send a `HEAD` request.
+    fn content_length(&self, request: &String) -> Result<Option<usize>, attohttpc::Error> {
+        let response = self.head(request).send()?.error_for_status()?;
+        Ok(response
+            .headers()
+            .get("Content-Length")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse().ok()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_impl::check_range_client;
+
+    #[test]
+    fn range_client() {
+        check_range_client(&attohttpc::Session::new(), |url| url);
+    }
+}
@@ -0,0 +1,520 @@
+use std::pin::Pin;
+
+use bytes::{Buf, Bytes};
+pub use http1;
+use http1::{header, HeaderMap, Method, StatusCode};
+pub use http_body;
+use http_body::Body;
+use http_body_util::{BodyExt, Full};
+use thiserror::Error;
+
+use crate::async_response::AsyncMaybePartialResponse;
+use crate::request::{IfRange, RangeHeader, IF_RANGE, RANGE};
+use crate::response::{
+    part_description, single_value, PartDesc, PartParseError, PartialHeaderParseError,
+    RangeResponse, Representation, ResponsePart, CONTENT_RANGE, CONTENT_TYPE,
+};
+use crate::streaming::{Event, PartsDecoder};
+
+fn header_values<'a>(headers: &'a HeaderMap, name: &str) -> Vec<&'a str> {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .collect()
+}
+
+impl<B> AsyncMaybePartialResponse for http1::Response<B>
+where
+    B: Body + Send,
+    B::Data: Send,
+    B::Error: std::error::Error + Send + Sync + 'static,
+{
+    type Error = B::Error;
+
+    fn status_code(&self) -> u16 {
+        self.status().as_u16()
+    }
+
+    fn header_values(&self, name: &str) -> Vec<&str> {
+        header_values(self.headers(), name)
+    }
+
+    /// Collect every frame of the body.
+    async fn body(self) -> Result<Bytes, B::Error> {
+        Ok(self.into_body().collect().await?.to_bytes())
+    }
+}
+
+/// Error from receiving parts with [BodyParts].
+#[derive(Debug, Error)]
+pub enum BodyPartsError<E> {
+    #[error(transparent)]
+    Body(E),
+    #[error(transparent)]
+    Part(#[from] PartParseError),
+}
+
+/// Receives the parts of a 206 Partial response from its [Body] as the frames arrive,
+/// rather than collecting the whole body first.
+///
+/// Each part is returned as soon as all of its data has been received,
+/// so only the part in progress is buffered.
+/// As with [StreamingParts](crate::streaming::StreamingParts), multipart bodies must use `CRLF` line endings.
+pub struct BodyParts<B> {
+    body: Pin<Box<B>>,
+    decoder: PartsDecoder,
+}
+
+impl<B> BodyParts<B>
+where
+    B: Body,
+    B::Error: std::error::Error + Send + Sync + 'static,
+{
+    pub fn new(part_desc: PartDesc, body: B) -> Self {
+        Self {
+            body: Box::pin(body),
+            decoder: PartsDecoder::new(part_desc),
+        }
+    }
+
+    /// If the response is a 206 Partial, its parts.
+    pub fn from_response(response: http1::Response<B>) -> Result<Self, PartialHeaderParseError> {
+        let headers = response.headers();
+        let part_desc = part_description(
+            response.status().as_u16(),
+            single_value(CONTENT_TYPE, header_values(headers, CONTENT_TYPE))?,
+            single_value(CONTENT_RANGE, header_values(headers, CONTENT_RANGE))?,
+        )?;
+        Ok(Self::new(part_desc, response.into_body()))
+    }
+
+    /// Receive the next part, or [None] after the last.
+    ///
+    /// Once an error has been returned, there are no more parts.
+    pub async fn next_part(&mut self) -> Result<Option<ResponsePart>, BodyPartsError<B::Error>> {
+        let mut head = None;
+        let mut data = Vec::default();
+        loop {
+            match self.decoder.next_event()? {
+                Event::NeedData => self.fill().await?,
+                Event::Part(h) => head = Some(h),
+                Event::Data(d) => data.push(d),
+                Event::PartEnd => break,
+                Event::Done => return Ok(None),
+            }
+        }
+        let head = head.expect("part headers come before its end");
+        // the data is usually received in one piece
+        let data = match data.len() {
+            1 => data.pop().expect("one piece"),
+            _ => data.concat().into(),
+        };
+        let part = ResponsePart::new(head.content_type, head.content_range, data);
+        Ok(Some(part.with_headers(head.headers)))
+    }
+
+    /// Feed the next frame of data to the decoder, or tell it the body has ended.
+    async fn fill(&mut self) -> Result<(), BodyPartsError<B::Error>> {
+        while let Some(frame) = self.body.frame().await {
+            let frame = frame.map_err(|e| {
+                self.decoder.abort();
+                BodyPartsError::Body(e)
+            })?;
+            // trailers and empty frames are skipped
+            if let Ok(data) = frame.into_data() {
+                if data.has_remaining() {
+                    self.decoder.feed(data);
+                    return Ok(());
+                }
+            }
+        }
+        self.decoder.finish();
+        Ok(())
+    }
+}
+
+/// The `Range` of a request for a server to apply, with its `If-Range` if it has one.
+///
+/// A missing or invalid `Range` gives an empty [RangeHeader], meaning the whole representation should be sent,
+/// as does one whose `If-Range` can never match (see [IfRange::parse]).
+/// Otherwise, the ranges should only be applied if the representation matches the [IfRange].
+pub fn request_range<B>(request: &http1::Request<B>) -> (RangeHeader<'_>, Option<IfRange>) {
+    let headers = request.headers();
+    let Ok(Some(range)) = single_value(RANGE, header_values(headers, RANGE)) else {
+        return (RangeHeader::default(), None);
+    };
+    let range = RangeHeader::parse(range).unwrap_or_default();
+    match single_value(IF_RANGE, header_values(headers, IF_RANGE)) {
+        Ok(None) => (range, None),
+        Ok(Some(value)) => match IfRange::parse(value) {
+            Some(if_range) => (range, Some(if_range)),
+            None => (RangeHeader::default(), None),
+        },
+        Err(_) => (RangeHeader::default(), None),
+    }
+}
+
+/// Answers requests for a representation held in memory as a server with range support would,
+/// from a [hyper](https://docs.rs/hyper) service or anything else which handles [http1::Request]s.
+///
+/// `GET` requests are answered as by a [Representation]: with single-part and `multipart/byteranges` 206 responses,
+/// 416 responses for unsatisfiable ranges, and the whole representation if the `If-Range` does not match.
+/// `HEAD` requests get the headers of the whole representation, and other methods a 405.
+///
+/// ```rust
+/// # use byteranges::http1;
+/// # use byteranges::response::{Bytes, RangeResponder};
+/// let responder = RangeResponder::new(Bytes::from_static(b"0123456789"));
+/// let request = http1::Request::get("/").header("Range", "bytes=2-4").body(()).unwrap();
+/// let response = responder.respond(&request);
+/// assert_eq!(response.status(), 206);
+/// assert_eq!(response.headers()["Content-Range"], "bytes 2-4/10");
+/// ```
+#[derive(Debug)]
+pub struct RangeResponder {
+    representation: Representation,
+}
+
+impl RangeResponder {
+    pub fn new(data: Bytes) -> Self {
+        Representation::new(data).into()
+    }
+
+    /// The representation being served.
+    pub fn representation(&self) -> &Representation {
+        &self.representation
+    }
+
+    /// Change the representation, e.g. to simulate it changing between requests.
+    pub fn representation_mut(&mut self) -> &mut Representation {
+        &mut self.representation
+    }
+
+    /// The response to the request, whose body is ignored.
+    pub fn respond<B>(&self, request: &http1::Request<B>) -> http1::Response<Full<Bytes>> {
+        let method = request.method();
+        if method != Method::GET && method != Method::HEAD {
+            return http1::Response::builder()
+                .status(StatusCode::METHOD_NOT_ALLOWED)
+                .header(header::ALLOW, "GET, HEAD")
+                .body(Full::default())
+                .expect("valid response");
+        }
+        let (mut range, if_range) = request_range(request);
+        if method == Method::HEAD {
+            range = RangeHeader::default();
+        }
+        let response = match self.representation.respond(&range, if_range.as_ref()) {
+            Ok(r) => r,
+            Err(_) => {
+                return http1::Response::builder()
+                    .status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .body(Full::default())
+                    .expect("valid response")
+            }
+        };
+        into_response(response, method != Method::HEAD)
+    }
+}
+
+impl From<Representation> for RangeResponder {
+    fn from(representation: Representation) -> Self {
+        Self { representation }
+    }
+}
+
+fn into_response(response: RangeResponse, body: bool) -> http1::Response<Full<Bytes>> {
+    let mut builder = http1::Response::builder().status(response.status);
+    for (name, value) in response.headers.iter() {
+        builder = builder.header(name, &value[..]);
+    }
+    let body = if body { response.body } else { Bytes::new() };
+    builder.body(Full::new(body)).expect("valid response")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::convert::Infallible;
+    use std::task::{Context, Poll};
+
+    use http_body::Frame;
+    use hyper_util::rt::TokioIo;
+
+    use super::*;
+    use crate::request::{EntityTag, Validator};
+    use crate::response::{MaybePartialResponse, SparseBody, SparseBodyError};
+    use crate::test_impl::{
+        fixture_names, lorem, parse_fixture, read_text, summarise, test_fixture, Summary,
+        FIXTURE_DIRS,
+    };
+
+    /// A body which arrives in frames of the given size.
+    struct Chunked(VecDeque<Bytes>);
+
+    impl Chunked {
+        fn new(data: Bytes, size: usize) -> Self {
+            let chunks = (0..data.len())
+                .step_by(size)
+                .map(|i| data.slice(i..(i + size).min(data.len())))
+                .collect();
+            Self(chunks)
+        }
+    }
+
+    impl Body for Chunked {
+        type Data = Bytes;
+        type Error = Infallible;
+
+        fn poll_frame(
+            mut self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+        ) -> Poll<Option<Result<Frame<Bytes>, Infallible>>> {
+            Poll::Ready(self.0.pop_front().map(|c| Ok(Frame::data(c))))
+        }
+    }
+
+    fn fixture_response(dir: &str, fname: &str, size: usize) -> http1::Response<Chunked> {
+        let (status, headers, body) = parse_fixture(dir, fname);
+        let mut builder = http1::Response::builder().status(status);
+        for (name, value) in headers {
+            builder = builder.header(name, value);
+        }
+        builder.body(Chunked::new(Bytes::from(body), size)).unwrap()
+    }
+
+    async fn summarise_async<R: AsyncMaybePartialResponse>(response: R) -> Result<Summary, String> {
+        use std::io::Read;
+        let mut bod = response.sparse_body().await.map_err(|e| e.to_string())?;
+        let mut data = Vec::default();
+        bod.read_to_end(&mut data).unwrap();
+        Ok((bod.populated().to_vec(), bod.validator(), data))
+    }
+
+    #[tokio::test]
+    async fn collect_fixtures() {
+        for dir in FIXTURE_DIRS {
+            for fname in fixture_names(dir) {
+                let expected = test_fixture(dir, &fname, |r| summarise(r));
+                let found = summarise_async(fixture_response(dir, &fname, 100)).await;
+                assert_eq!(found, expected, "{dir}/{fname}");
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn merge_responses_async() {
+        let reference = read_text();
+        let mut bod = SparseBody::new();
+        for fname in ["bytes=50-100", "bytes=-100", "bytes=3000-"] {
+            let resp = fixture_response("response", fname, 100);
+            bod.insert_response_async(resp).await.unwrap();
+        }
+        assert_eq!(bod.len(), reference.len());
+        assert_eq!(bod.populated(), &[(50, 51), (3000, 1057)]);
+        assert_eq!(bod.sources().len(), 3);
+
+        let changed = http1::Response::builder()
+            .status(200)
+            .header("ETag", "\"other\"")
+            .body(Chunked::new(Bytes::from(reference), 100))
+            .unwrap();
+        let err = bod.insert_response_async(changed).await.unwrap_err();
+        assert!(matches!(err, SparseBodyError::ValidatorMismatch { .. }));
+        assert_eq!(bod.populated(), &[(50, 51), (3000, 1057)]);
+    }
+
+    async fn stream_parts<B>(mut parts: BodyParts<B>) -> Result<Vec<ResponsePart>, String>
+    where
+        B: Body,
+        B::Error: std::error::Error + Send + Sync + 'static,
+    {
+        let mut out = Vec::default();
+        while let Some(part) = parts.next_part().await.map_err(|e| e.to_string())? {
+            out.push(part);
+        }
+        Ok(out)
+    }
+
+    #[tokio::test]
+    async fn stream_fixtures() {
+        let reference = read_text();
+        for fname in [
+            "numeric", "hex", "quoted", "long", "params", "preamble", "epilogue",
+        ] {
+            let expected: Vec<_> = test_fixture("multipart", fname, |r| {
+                r.parts().unwrap().map(|p| p.unwrap()).collect()
+            });
+            // frames small enough to split the delimiters
+            for size in [1, 3, 64, 100_000] {
+                let parts = BodyParts::from_response(fixture_response("multipart", fname, size));
+                let found = stream_parts(parts.unwrap()).await.unwrap();
+                assert_eq!(found.len(), expected.len(), "{fname} {size}");
+                for (f, e) in found.iter().zip(expected.iter()) {
+                    assert_eq!(f.offset_len(), e.offset_len(), "{fname} {size}");
+                    assert_eq!(f.data(), e.data(), "{fname} {size}");
+                    assert_eq!(f.headers(), e.headers(), "{fname} {size}");
+                    let (offset, len) = f.offset_len().unwrap();
+                    assert_eq!(f.data(), &reference[offset..offset + len]);
+                }
+            }
+        }
+
+        let parts = BodyParts::from_response(fixture_response("response", "bytes=50-100", 7));
+        let found = stream_parts(parts.unwrap()).await.unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].data(), &reference[50..101]);
+
+        let parts = BodyParts::from_response(fixture_response("handmade", "full", 7));
+        assert!(matches!(
+            parts,
+            Err(PartialHeaderParseError::NotPartialResponse(200))
+        ));
+    }
+
+    #[tokio::test]
+    async fn stream_malformed() {
+        for (body, expected) in crate::test_impl::malformed_bodies() {
+            let part_desc = PartDesc::Multi {
+                boundary: b"--B".to_vec(),
+            };
+            let mut parts = BodyParts::new(part_desc, Chunked::new(Bytes::from(body.clone()), 5));
+            let err = loop {
+                match parts.next_part().await {
+                    Ok(Some(_)) => (),
+                    Ok(None) => panic!("no error for {body:?}"),
+                    Err(e) => break e,
+                }
+            };
+            assert!(
+                matches!(&err, BodyPartsError::Part(e) if *e == expected),
+                "{err} for {body:?}"
+            );
+            assert!(parts.next_part().await.unwrap().is_none());
+        }
+    }
+
+    fn responder() -> RangeResponder {
+        lorem().into()
+    }
+
+    fn request(method: Method, headers: &[(&str, &str)]) -> http1::Request<()> {
+        let mut builder = http1::Request::builder().method(method).uri("/");
+        for (name, value) in headers {
+            builder = builder.header(*name, *value);
+        }
+        builder.body(()).unwrap()
+    }
+
+    #[test]
+    fn request_ranges() {
+        let req = request(
+            Method::GET,
+            &[("Range", "bytes=0-9"), ("If-Range", "\"1\"")],
+        );
+        let (range, if_range) = request_range(&req);
+        assert_eq!(range.to_string(), "bytes=0-9");
+        assert_eq!(
+            if_range.unwrap().validator(),
+            &Validator::ETag(EntityTag::strong("1").unwrap())
+        );
+
+        for headers in [
+            &[][..],
+            &[("Range", "0-9")],
+            &[("Range", "bytes=0-9"), ("Range", "bytes=5-9")],
+            &[("Range", "bytes=0-9"), ("If-Range", "W/\"1\"")],
+        ] {
+            let req = request(Method::GET, headers);
+            let (range, if_range) = request_range(&req);
+            assert!(range.is_empty(), "{headers:?}");
+            assert!(if_range.is_none(), "{headers:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn respond() {
+        let reference = read_text();
+        let responder = responder();
+
+        let resp = responder.respond(&request(Method::GET, &[("Range", "bytes=0-9,-10")]));
+        assert_eq!(resp.status(), 206);
+        let bod = resp.sparse_body().await.unwrap();
+        assert_eq!(bod.populated(), &[(0, 10), (4047, 10)]);
+
+        let resp = responder.respond(&request(Method::GET, &[("Range", "bytes=5000-")]));
+        assert_eq!(resp.status(), 416);
+        assert_eq!(resp.headers()["Content-Range"], "bytes */4057");
+
+        let headers = [("Range", "bytes=0-9"), ("If-Range", "\"2\"")];
+        let resp = responder.respond(&request(Method::GET, &headers));
+        assert_eq!(resp.status(), 200);
+        assert_eq!(
+            AsyncMaybePartialResponse::body(resp).await.unwrap(),
+            reference
+        );
+
+        let resp = responder.respond(&request(Method::HEAD, &[("Range", "bytes=0-9")]));
+        assert_eq!(resp.status(), 200);
+        assert_eq!(resp.headers()["Content-Length"], "4057");
+        assert!(AsyncMaybePartialResponse::body(resp)
+            .await
+            .unwrap()
+            .is_empty());
+
+        let resp = responder.respond(&request(Method::POST, &[]));
+        assert_eq!(resp.status(), 405);
+        assert_eq!(resp.headers()["Allow"], "GET, HEAD");
+    }
+
+    #[tokio::test]
+    async fn hyper_service() {
+        let reference = read_text();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let responder = std::sync::Arc::new(responder());
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let service = hyper::service::service_fn(move |request| {
+                let responder = std::sync::Arc::clone(&responder);
+                async move { Ok::<_, Infallible>(responder.respond(&request)) }
+            });
+            hyper::server::conn::http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+                .unwrap();
+        });
+
+        let stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let (mut sender, conn) = hyper::client::conn::http1::handshake(TokioIo::new(stream))
+            .await
+            .unwrap();
+        tokio::spawn(conn);
+        let send = |range: &str| {
+            http1::Request::get("/")
+                .header(header::HOST, addr.to_string())
+                .header(RANGE, range)
+                .body(http_body_util::Empty::<Bytes>::new())
+                .unwrap()
+        };
+
+        let resp = sender.send_request(send("bytes=50-100")).await.unwrap();
+        let bod = resp.sparse_body().await.unwrap();
+        assert_eq!(bod.populated(), &[(50, 51)]);
+
+        let resp = sender
+            .send_request(send("bytes=0-49,1000-1099,4000-"))
+            .await
+            .unwrap();
+        let found = stream_parts(BodyParts::from_response(resp).unwrap())
+            .await
+            .unwrap();
+        let spans: Vec<_> = found.iter().map(|p| p.offset_len().unwrap()).collect();
+        assert_eq!(spans, [(0, 50), (1000, 100), (4000, 57)]);
+        for part in found {
+            let (offset, len) = part.offset_len().unwrap();
+            assert_eq!(part.data(), &reference[offset..offset + len]);
+        }
+    }
+}
@@ -3,32 +3,262 @@ use std::{error::Error, io::Read};
 use bytes::Bytes;
 pub use http;
 
+use crate::client::RangeClient;
+use crate::request::{IfRange, RangeHeader, IF_RANGE, RANGE};
 use crate::response::MaybePartialResponse;
 
 impl<T: Read> MaybePartialResponse for http::Response<T> {
+    type Error = std::io::Error;
+
     fn status_code(&self) -> u16 {
         self.status().as_u16()
     }
 
-    fn content_type_str(&self) -> Option<&str> {
-        self.headers()
-            .get("Content-Type")
-            .and_then(|v| v.to_str().ok())
-    }
-
-    fn content_range_str(&self) -> Option<&str> {
+    fn header_values(&self, name: &str) -> Vec<&str> {
         self.headers()
-            .get("Content-Type")
-            .and_then(|v| v.to_str().ok())
+            .get_all(name)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .collect()
     }
 
-    fn body(self) -> Result<bytes::Bytes, Box<dyn std::error::Error>> {
+    fn body(self) -> Result<bytes::Bytes, std::io::Error> {
         let mut rd = self.into_body();
         let mut buf = Vec::default();
-        rd.read_to_end(&mut buf).map_err(|e| {
-            let b: Box<dyn Error> = Box::new(e);
-            b
-        })?;
+        rd.read_to_end(&mut buf)?;
         Ok(Bytes::from(buf))
     }
+
+    fn body_reader<'a>(self) -> Result<Box<dyn Read + 'a>, std::io::Error>
+    where
+        Self: 'a,
+    {
+        Ok(Box::new(self.into_body()))
+    }
+}
+
+/// A [RangeClient] for any client which sends [http::Request]s, given as a function.
+///
+/// The function is called with a `GET` request for the URI, with the range headers set;
+/// its error is the [RangeClient::Error].
+pub struct HttpClient<F>(pub F);
+
+impl<F, T, E> RangeClient for HttpClient<F>
+where
+    F: Fn(http::Request<()>) -> Result<http::Response<T>, E>,
+    T: Read,
+    E: Error + Send + Sync + 'static,
+{
+    type Request = http::Uri;
+    type Response = http::Response<T>;
+    type Error = E;
+
+    fn send(
+        &self,
+        request: &http::Uri,
+        range: &RangeHeader,
+        if_range: Option<&IfRange>,
+    ) -> Result<Self::Response, E> {
+        let mut builder = http::Request::get(request.clone());
+        if !range.is_empty() {
+            builder = builder.header(RANGE, range.to_value());
+        }
+        if let Some(if_range) = if_range {
+            builder = builder.header(IF_RANGE, if_range.to_value());
+        }
+        (self.0)(builder.body(()).expect("valid request"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::Infallible, io::Cursor};
+
+    use super::*;
+    use crate::client::{ClientTransport, MockClient};
+    use crate::remote::{RemoteFile, RemoteFileError};
+    use crate::request::EntityTag;
+    use crate::response::{PartialHeaderParseError, SparseBodyError};
+    use crate::test_impl::{
+        fixture_names, lorem, parse_fixture, summarise, test_fixture, FIXTURE_DIRS,
+    };
+
+    type Response = http::Response<Cursor<Bytes>>;
+
+    fn fixture_response(dir: &str, fname: &str) -> Response {
+        let (status, headers, body) = parse_fixture(dir, fname);
+        let mut builder = http::Response::builder().status(status);
+        for (name, value) in headers {
+            builder = builder.header(name, value);
+        }
+        builder.body(Cursor::new(Bytes::from(body))).unwrap()
+    }
+
+    #[test]
+    fn fixtures() {
+        for dir in FIXTURE_DIRS {
+            for fname in fixture_names(dir) {
+                let expected = test_fixture(dir, &fname, |r| summarise(r));
+                let found = summarise(fixture_response(dir, &fname));
+                assert_eq!(found, expected, "{dir}/{fname}");
+            }
+        }
+    }
+
+    struct FailingReader;
+
+    impl Read for FailingReader {
+        fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> {
+            Err(std::io::Error::other("connection reset"))
+        }
+    }
+
+    #[test]
+    fn body_error() {
+        let resp = http::Response::builder()
+            .status(200)
+            .body(FailingReader)
+            .unwrap();
+        let err = resp.sparse_body().err().unwrap();
+        assert!(matches!(&err, SparseBodyError::Body(e) if e.to_string() == "connection reset"));
+        // the error can be sent to another thread
+        let err: Box<dyn Error + Send + Sync> = Box::new(err);
+        let msg = std::thread::spawn(move || err.to_string()).join().unwrap();
+        assert_eq!(msg, "connection reset");
+
+        let resp = http::Response::builder()
+            .status(206)
+            .header("Content-Type", "text/plain")
+            .header("Content-Range", "bytes 0-2/10")
+            .body(FailingReader)
+            .unwrap();
+        let err = resp.parts().err().unwrap();
+        assert!(
+            matches!(err, PartialHeaderParseError::BodyRead(e) if e.kind() == std::io::ErrorKind::Other)
+        );
+    }
+
+    #[test]
+    fn streams_parts() {
+        let first =
+            b"--B\r\nContent-Type: text/plain\r\nContent-Range: bytes 0-2/10\r\n\r\nabc\r\n--B";
+        let resp = http::Response::builder()
+            .status(206)
+            .header("Content-Type", "multipart/byteranges; boundary=B")
+            .body(Read::chain(&first[..], FailingReader))
+            .unwrap();
+        // the first part is available before the body fails
+        let mut parts = resp.streaming_parts().unwrap();
+        let part = parts.next_part().unwrap().unwrap();
+        assert_eq!(part.into_response_part().unwrap().data(), &b"abc"[..]);
+        assert!(parts.next_part().is_err());
+    }
+
+    #[test]
+    fn single_part() {
+        let resp = fixture_response("response", "bytes=50-100");
+        assert_eq!(resp.content_range_str(), Some("bytes 50-100/4057"));
+        let bod = resp.sparse_body().unwrap();
+        assert_eq!(bod.populated(), &[(50, 51)]);
+    }
+
+    #[test]
+    fn repeated_content_range() {
+        let response = |ranges: &[&str]| -> Response {
+            let mut builder = http::Response::builder()
+                .status(206)
+                .header("Content-Type", "text/plain");
+            for range in ranges {
+                builder = builder.header("Content-Range", *range);
+            }
+            builder
+                .body(Cursor::new(Bytes::from_static(b"abc")))
+                .unwrap()
+        };
+
+        let resp = response(&["bytes 0-2/10", "bytes 0-2/10"]);
+        assert_eq!(resp.header_values("content-range").len(), 2);
+        assert_eq!(resp.sparse_body().unwrap().populated(), &[(0, 3)]);
+
+        let resp = response(&["bytes 0-2/10", "bytes 5-7/10"]);
+        assert_eq!(
+            resp.header_values("CONTENT-RANGE"),
+            ["bytes 0-2/10", "bytes 5-7/10"]
+        );
+        assert_eq!(resp.content_range_str(), None);
+        assert!(matches!(
+            resp.part_description(),
+            Err(PartialHeaderParseError::ConflictingHeaders { name, .. }) if name == "Content-Range"
+        ));
+    }
+
+    /// Answer a request with a [MockClient], recording its headers.
+    fn mock_client(
+        requests: &std::sync::Mutex<Vec<http::HeaderMap>>,
+    ) -> HttpClient<impl Fn(http::Request<()>) -> Result<Response, Infallible> + '_> {
+        let mock = MockClient::from(lorem());
+        HttpClient(move |request: http::Request<()>| {
+            let header = |name| {
+                request
+                    .headers()
+                    .get(name)
+                    .map(|v| v.to_str().unwrap().to_owned())
+            };
+            let range = header(RANGE).unwrap_or_default();
+            let range = RangeHeader::parse(&range).unwrap_or_default();
+            let if_range =
+                header(IF_RANGE).map(|v| IfRange::new(v.parse::<EntityTag>().unwrap()).unwrap());
+            let response = mock.send(&(), &range, if_range.as_ref()).unwrap();
+            requests.lock().unwrap().push(request.headers().clone());
+
+            let mut builder = http::Response::builder().status(response.status());
+            for (name, value) in response.headers() {
+                builder = builder.header(name, &value[..]);
+            }
+            Ok(builder.body(Cursor::new(response.data().clone())).unwrap())
+        })
+    }
+
+    #[test]
+    fn range_client() {
+        let requests = std::sync::Mutex::default();
+        let client = mock_client(&requests);
+        let uri = http::Uri::from_static("http://localhost/lorem.txt");
+        let if_range = IfRange::new(EntityTag::strong("1").unwrap()).unwrap();
+        let range = RangeHeader::parse("bytes=0-9,-10").unwrap();
+
+        let resp = client.send(&uri, &range, Some(&if_range)).unwrap();
+        assert_eq!(resp.status(), 206);
+        let bod = resp.sparse_body().unwrap();
+        assert_eq!(bod.populated(), &[(0, 10), (4047, 10)]);
+
+        let resp = client.send(&uri, &RangeHeader::default(), None).unwrap();
+        assert_eq!(resp.status(), 200);
+
+        drop(client);
+        let requests = requests.into_inner().unwrap();
+        assert_eq!(requests[0][RANGE], "bytes=0-9,-10");
+        assert_eq!(requests[0][IF_RANGE], "\"1\"");
+        assert!(requests[1].is_empty());
+    }
+
+    #[test]
+    fn client_error() {
+        let client = HttpClient(|_: http::Request<()>| -> Result<Response, std::io::Error> {
+            Err(std::io::Error::new(
+                std::io::ErrorKind::ConnectionRefused,
+                "refused",
+            ))
+        });
+        let uri = http::Uri::from_static("http://localhost/lorem.txt");
+        let err = client.send(&uri, &(0..10).into(), None).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::ConnectionRefused);
+
+        let mut file = RemoteFile::new(ClientTransport::new(client, uri));
+        let err = file.read(&mut [0; 10]).unwrap_err();
+        let Some(RemoteFileError::Transport(e)) = RemoteFileError::from_io(&err) else {
+            panic!("{err}");
+        };
+        assert_eq!(e.to_string(), "refused");
+    }
 }
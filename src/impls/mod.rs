@@ -3,10 +3,25 @@ mod reqwest_impl;
 #[cfg(feature = "reqwest")]
 pub use reqwest_impl::reqwest;
 
+#[cfg(feature = "ureq")]
+mod ureq_impl;
+#[cfg(feature = "ureq")]
+pub use ureq_impl::ureq;
+
+#[cfg(feature = "attohttpc")]
+mod attohttpc_impl;
+#[cfg(feature = "attohttpc")]
+pub use attohttpc_impl::attohttpc;
+
 #[cfg(feature = "http")]
-mod http_impl;
+pub(crate) mod http_impl;
 #[cfg(feature = "http")]
 pub use http_impl::http;
 
+#[cfg(feature = "http-body")]
+pub(crate) mod http_body_impl;
+#[cfg(feature = "http-body")]
+pub use http_body_impl::{http1, http_body};
+
 #[cfg(test)]
 pub(crate) mod test_impl;
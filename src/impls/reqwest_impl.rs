@@ -1,29 +1,132 @@
-use std::error::Error;
+use std::io::Read;
 
 pub use reqwest;
-use reqwest::blocking::Response;
+use reqwest::blocking::{Client, Response};
+use reqwest::Url;
+
+use crate::client::RangeClient;
+use crate::request::{IfRange, RangeHeader, IF_RANGE, RANGE};
 
 impl crate::response::MaybePartialResponse for Response {
+    type Error = reqwest::Error;
+
     fn status_code(&self) -> u16 {
         self.status().as_u16()
     }
 
-    fn content_type_str(&self) -> Option<&str> {
+    fn header_values(&self, name: &str) -> Vec<&str> {
         self.headers()
-            .get("Content-Type")
+            .get_all(name)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .collect()
+    }
+
+    fn body(self) -> Result<bytes::Bytes, reqwest::Error> {
+        self.bytes()
+    }
+
+    fn body_reader<'a>(self) -> Result<Box<dyn Read + 'a>, reqwest::Error>
+    where
+        Self: 'a,
+    {
+        Ok(Box::new(self))
+    }
+}
+
+impl RangeClient for Client {
+    type Request = Url;
+    type Response = Response;
+    type Error = reqwest::Error;
+
+    fn send(
+        &self,
+        request: &Url,
+        range: &RangeHeader,
+        if_range: Option<&IfRange>,
+    ) -> Result<Response, reqwest::Error> {
+        let mut builder = self.get(request.clone());
+        if !range.is_empty() {
+            builder = builder.header(RANGE, range.to_value());
+        }
+        if let Some(if_range) = if_range {
+            builder = builder.header(IF_RANGE, if_range.to_value());
+        }
+        builder.send()
+    }
+
+    /// Send a `HEAD` request.
+    fn content_length(&self, request: &Url) -> Result<Option<usize>, reqwest::Error> {
+        let response = self.head(request.clone()).send()?.error_for_status()?;
+        // the body of a HEAD response is empty, so read the header rather than the body's length
+        Ok(response
+            .headers()
+            .get("Content-Length")
             .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse().ok()))
+    }
+}
+
+#[cfg(feature = "async")]
+impl crate::async_response::AsyncMaybePartialResponse for reqwest::Response {
+    type Error = reqwest::Error;
+
+    fn status_code(&self) -> u16 {
+        self.status().as_u16()
     }
 
-    fn content_range_str(&self) -> Option<&str> {
+    fn header_values(&self, name: &str) -> Vec<&str> {
         self.headers()
-            .get("Content-Range")
-            .and_then(|v| v.to_str().ok())
+            .get_all(name)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .collect()
+    }
+
+    async fn body(self) -> Result<bytes::Bytes, reqwest::Error> {
+        self.bytes().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "async")]
+    use std::io::{Read, Seek, SeekFrom};
+
+    #[cfg(feature = "async")]
+    use crate::async_response::AsyncMaybePartialResponse;
+    use crate::test_impl::check_range_client;
+    #[cfg(feature = "async")]
+    use crate::test_impl::{read_text, serve_response};
+
+    #[test]
+    fn range_client() {
+        check_range_client(&reqwest::blocking::Client::new(), |url| {
+            url.parse().unwrap()
+        });
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn async_sparse_body() {
+        let reference = read_text();
+        let url = serve_response("bytes=3000-");
+        let resp = reqwest::get(url).await.unwrap();
+        let mut bod = resp.sparse_body().await.unwrap();
+        let mut buf = [255; 200];
+        bod.seek(SeekFrom::Start(2900)).unwrap();
+        bod.read_exact(&mut buf).unwrap();
+        assert_eq!(buf[..100], [0; 100]);
+        assert_eq!(buf[100..], reference[3000..3100]);
     }
 
-    fn body(self) -> Result<bytes::Bytes, Box<dyn Error>> {
-        self.bytes().map_err(|e| {
-            let e: Box<dyn Error> = Box::new(e);
-            e
-        })
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn async_parts() {
+        let url = serve_response("bytes=50-100");
+        let resp = reqwest::get(url).await.unwrap();
+        let parts: Vec<_> = resp.parts().await.unwrap().collect();
+        assert_eq!(parts.len(), 1);
+        assert_eq!(parts[0].as_ref().unwrap().offset_len(), Some((50, 51)));
     }
 }
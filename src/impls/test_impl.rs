@@ -1,12 +1,23 @@
 use bytes::Bytes;
 use httparse::EMPTY_HEADER;
+use std::convert::Infallible;
 use std::fs;
 use std::io::Read;
 use std::path::PathBuf;
 
 use httparse::{Header, Response};
 
-use crate::response::MaybePartialResponse;
+use crate::request::EntityTag;
+use crate::response::{MaybePartialResponse, PartParseError, PartParseErrorKind, Representation};
+
+/// `lorem.txt` as served by the mocks in tests: `text/plain`, with the strong `ETag` `"1"`.
+pub fn lorem() -> Representation {
+    let mut representation = Representation::new(Bytes::from(read_text()));
+    representation
+        .set_content_type("text/plain")
+        .set_etag(Some(EntityTag::strong("1").unwrap()));
+    representation
+}
 
 pub fn read_text() -> Vec<u8> {
     let mut p = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
@@ -18,10 +29,20 @@ pub fn read_text() -> Vec<u8> {
     v
 }
 
-fn read_response(fname: &str) -> Vec<u8> {
+/// Subdirectories of `data/` containing responses: see `data/README.md`.
+#[cfg(any(
+    feature = "http",
+    feature = "http-body",
+    feature = "reqwest",
+    feature = "ureq",
+    feature = "attohttpc"
+))]
+pub const FIXTURE_DIRS: [&str; 3] = ["response", "handmade", "multipart"];
+
+fn read_fixture(dir: &str, fname: &str) -> Vec<u8> {
     let mut p = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
     p.push("data");
-    p.push("response");
+    p.push(dir);
     p.push(format!("{fname}.http1"));
     let mut v = Vec::default();
     let mut f = fs::File::open(p).unwrap();
@@ -29,6 +50,214 @@ fn read_response(fname: &str) -> Vec<u8> {
     v
 }
 
+/// Names of the fixtures in a subdirectory of `data/`, without their extension.
+#[cfg(any(
+    feature = "http",
+    feature = "http-body",
+    feature = "reqwest",
+    feature = "ureq",
+    feature = "attohttpc"
+))]
+pub fn fixture_names(dir: &str) -> Vec<String> {
+    let mut p = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    p.push("data");
+    p.push(dir);
+    let mut names: Vec<_> = fs::read_dir(p)
+        .unwrap()
+        .filter_map(|entry| {
+            let fname = entry.unwrap().file_name().into_string().unwrap();
+            fname.strip_suffix(".http1").map(str::to_owned)
+        })
+        .collect();
+    names.sort();
+    names
+}
+
+/// The status code, headers and body of a fixture.
+#[cfg(any(feature = "http", feature = "http-body"))]
+pub type Fixture = (u16, Vec<(String, Vec<u8>)>, Vec<u8>);
+
+/// The [Fixture] parts of a fixture, for building responses of other types.
+#[cfg(any(feature = "http", feature = "http-body"))]
+pub fn parse_fixture(dir: &str, fname: &str) -> Fixture {
+    test_fixture(dir, fname, |r| {
+        let headers = r
+            .response
+            .headers
+            .iter()
+            .map(|h| (h.name.to_owned(), h.value.to_vec()))
+            .collect();
+        (r.status_code(), headers, r.body.to_vec())
+    })
+}
+
+/// The populated spans, validator and data of a response's [SparseBody](crate::response::SparseBody).
+#[cfg(any(
+    feature = "http",
+    feature = "http-body",
+    feature = "reqwest",
+    feature = "ureq",
+    feature = "attohttpc"
+))]
+pub type Summary = (
+    Vec<(usize, usize)>,
+    Option<crate::request::Validator>,
+    Vec<u8>,
+);
+
+/// What a response reads as, or its error, for comparing implementations.
+#[cfg(any(
+    feature = "http",
+    feature = "http-body",
+    feature = "reqwest",
+    feature = "ureq",
+    feature = "attohttpc"
+))]
+pub fn summarise<R: MaybePartialResponse>(response: R) -> Result<Summary, String> {
+    let mut bod = response.sparse_body().map_err(|e| e.to_string())?;
+    let mut data = Vec::default();
+    bod.read_to_end(&mut data).unwrap();
+    Ok((bod.populated().to_vec(), bod.validator(), data))
+}
+
+/// Serve the given fixture from `data/response/` once over HTTP on localhost, returning its URL.
+#[cfg(any(feature = "reqwest", feature = "ureq", feature = "attohttpc"))]
+pub fn serve_response(fname: &str) -> String {
+    serve_fixture("response", fname)
+}
+
+/// Like [serve_response], for a fixture in another subdirectory of `data/`.
+#[cfg(any(feature = "reqwest", feature = "ureq", feature = "attohttpc"))]
+pub fn serve_fixture(dir: &str, fname: &str) -> String {
+    use std::io::Write;
+    use std::net::TcpListener;
+
+    let raw = read_fixture(dir, fname);
+    // fixtures may have been checked out with LF line endings
+    let (head, body) = match raw.windows(4).position(|w| w == b"\r\n\r\n") {
+        Some(idx) => (&raw[..idx], &raw[idx + 4..]),
+        None => {
+            let idx = raw
+                .windows(2)
+                .position(|w| w == b"\n\n")
+                .expect("fixture has no end of headers");
+            (&raw[..idx], &raw[idx + 2..])
+        }
+    };
+    let mut response = Vec::default();
+    for line in head.split(|b| *b == b'\n') {
+        response.extend_from_slice(line.strip_suffix(b"\r").unwrap_or(line));
+        response.extend_from_slice(b"\r\n");
+    }
+    response.extend_from_slice(b"\r\n");
+    response.extend_from_slice(body);
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = Vec::default();
+        let mut buf = [0; 1024];
+        while !request.ends_with(b"\r\n\r\n") {
+            let n = stream.read(&mut buf).unwrap();
+            if n == 0 {
+                return;
+            }
+            request.extend_from_slice(&buf[..n]);
+        }
+        stream.write_all(&response).unwrap();
+    });
+    format!("http://{addr}/lorem.txt")
+}
+
+/// Check a [RangeClient] against fixtures served over HTTP on localhost,
+/// making its requests from their URLs with `request`.
+#[cfg(any(feature = "reqwest", feature = "ureq", feature = "attohttpc"))]
+pub fn check_range_client<C, F>(client: &C, request: F)
+where
+    C: crate::client::RangeClient,
+    F: Fn(String) -> C::Request,
+{
+    use crate::request::RangeHeader;
+    use std::io::{Seek, SeekFrom};
+
+    for dir in FIXTURE_DIRS {
+        for fname in fixture_names(dir) {
+            let expected = test_fixture(dir, &fname, |r| summarise(r));
+            let url = request(serve_fixture(dir, &fname));
+            let resp = client.send(&url, &RangeHeader::default(), None).unwrap();
+            assert_eq!(summarise(resp), expected, "{dir}/{fname}");
+        }
+    }
+
+    let url = request(serve_response("bytes=50-100"));
+    let resp = client.send(&url, &(50..101).into(), None).unwrap();
+    let parts: Vec<_> = resp.parts().unwrap().collect();
+    assert_eq!(parts.len(), 1);
+    assert_eq!(parts[0].as_ref().unwrap().offset_len(), Some((50, 51)));
+
+    let url = request(serve_fixture("handmade", "unsatisfiable"));
+    let resp = client.send(&url, &(5000..).into(), None).unwrap();
+    assert_eq!(resp.status_code(), 416);
+
+    let reference = read_text();
+    let url = request(serve_response("bytes=3000-"));
+    let resp = client.send(&url, &(3000..).into(), None).unwrap();
+    let mut bod = resp.sparse_body().unwrap();
+    let mut buf = [255; 200];
+    bod.seek(SeekFrom::Start(2900)).unwrap();
+    bod.read_exact(&mut buf).unwrap();
+    assert_eq!(buf[..100], [0; 100]);
+    assert_eq!(buf[100..], reference[3000..3100]);
+}
+
+/// Malformed multipart bodies with the boundary `B`,
+/// each with the error which should be returned after any valid parts.
+pub fn malformed_bodies() -> Vec<(Vec<u8>, PartParseError)> {
+    use PartParseErrorKind::*;
+    let part = |headers: &str, data: &str| format!("--B\r\n{headers}\r\n\r\n{data}\r\n");
+    let ct = "Content-Type: text/plain";
+    let cr = "Content-Range: bytes 0-2/10";
+    let valid = part(&format!("{ct}\r\n{cr}"), "abc");
+    let cases: Vec<(String, usize, PartParseErrorKind)> = vec![
+        ("no boundary".into(), 0, NoBoundary),
+        ("--Bxx".into(), 3, MissingCrlf),
+        ("--B".into(), 3, MissingCrlf),
+        (format!("{valid}--Bxx"), 70, MissingCrlf),
+        (format!("--B\r\n{ct}"), 5, TruncatedHeaders),
+        (format!("--B\r\n{ct}\r\n{cr}\r\n"), 5, TruncatedHeaders),
+        (part(ct, "abc") + "--B--", 5, NoContentRange),
+        (part(cr, "abc") + "--B--", 5, NoContentType),
+        (
+            part(&format!("{ct}\r\n{cr}"), "abcd") + "--B--",
+            62,
+            LengthMismatch {
+                expected: 3,
+                found: 4,
+            },
+        ),
+        (
+            format!("--B\r\n{ct}\r\n{cr}\r\n\r\nabc"),
+            62,
+            MissingDelimiter,
+        ),
+    ];
+    let mut out: Vec<_> = cases
+        .into_iter()
+        .map(|(body, offset, kind)| (body.into_bytes(), PartParseError { offset, kind }))
+        .collect();
+    let mut non_utf8 = b"--B\r\nContent-Type: \xff\r\n".to_vec();
+    non_utf8.extend_from_slice(format!("{cr}\r\n\r\nabc\r\n--B--").as_bytes());
+    out.push((
+        non_utf8,
+        PartParseError {
+            offset: 5,
+            kind: NonUtf8ContentType,
+        },
+    ));
+    out
+}
+
 pub fn header_buf() -> [Header<'static>; 64] {
     [EMPTY_HEADER; 64]
 }
@@ -54,36 +283,34 @@ impl<'a> DummyResponse<'a> {
 }
 
 pub fn test_response<T, F: FnOnce(DummyResponse) -> T>(fname: &str, test_fn: F) -> T {
+    test_fixture("response", fname, test_fn)
+}
+
+/// Like [test_response], for a fixture in another subdirectory of `data/`.
+pub fn test_fixture<T, F: FnOnce(DummyResponse) -> T>(dir: &str, fname: &str, test_fn: F) -> T {
     let mut hbuf = header_buf();
-    let mut buf = read_response(fname);
-    let tr = DummyResponse::new(&mut hbuf, &mut buf);
+    let buf = read_fixture(dir, fname);
+    let tr = DummyResponse::new(&mut hbuf, &buf);
     test_fn(tr)
 }
 
 impl<'a> MaybePartialResponse for DummyResponse<'a> {
+    type Error = Infallible;
+
     fn status_code(&self) -> u16 {
         self.response.code.unwrap()
     }
 
-    fn content_type_str(&self) -> Option<&str> {
-        for h in self.response.headers.iter() {
-            if h.name.to_lowercase() == "content-type" {
-                return Some(std::str::from_utf8(h.value).unwrap());
-            }
-        }
-        return None;
-    }
-
-    fn content_range_str(&self) -> Option<&str> {
-        for h in self.response.headers.iter() {
-            if h.name.to_lowercase() == "content-range" {
-                return Some(std::str::from_utf8(h.value).unwrap());
-            }
-        }
-        return None;
+    fn header_values(&self, name: &str) -> Vec<&str> {
+        self.response
+            .headers
+            .iter()
+            .filter(|h| h.name.eq_ignore_ascii_case(name))
+            .map(|h| std::str::from_utf8(h.value).unwrap())
+            .collect()
     }
 
-    fn body(self) -> Result<Bytes, Box<dyn std::error::Error>> {
+    fn body(self) -> Result<Bytes, Infallible> {
         Ok(Bytes::from_iter(self.body.iter().cloned()))
     }
 }
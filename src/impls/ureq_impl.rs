@@ -0,0 +1,77 @@
+use std::io::Read;
+
+pub use ureq;
+use ureq::{Agent, Response};
+
+use crate::client::RangeClient;
+use crate::request::{IfRange, RangeHeader, IF_RANGE, RANGE};
+
+impl crate::response::MaybePartialResponse for Response {
+    type Error = std::io::Error;
+
+    fn status_code(&self) -> u16 {
+        self.status()
+    }
+
+    fn header_values(&self, name: &str) -> Vec<&str> {
+        self.all(name)
+    }
+
+    fn body(self) -> Result<bytes::Bytes, std::io::Error> {
+        let mut buf = Vec::default();
+        self.into_reader().read_to_end(&mut buf)?;
+        Ok(buf.into())
+    }
+
+    fn body_reader<'a>(self) -> Result<Box<dyn Read + 'a>, std::io::Error>
+    where
+        Self: 'a,
+    {
+        Ok(self.into_reader())
+    }
+}
+
+/// ureq returns 4xx and 5xx responses as errors;
+/// they are returned as responses here so that a 416 can be handled like any other.
+impl RangeClient for Agent {
+    type Request = String;
+    type Response = Response;
+    type Error = ureq::Error;
+
+    fn send(
+        &self,
+        request: &String,
+        range: &RangeHeader,
+        if_range: Option<&IfRange>,
+    ) -> Result<Response, ureq::Error> {
+        let mut req = self.get(request);
+        if !range.is_empty() {
+            req = req.set(RANGE, &range.to_string());
+        }
+        if let Some(if_range) = if_range {
+            req = req.set(IF_RANGE, &if_range.to_string());
+        }
+        match req.call() {
+            Ok(response) | Err(ureq::Error::Status(_, response)) => Ok(response),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Send a `HEAD` request.
+    fn content_length(&self, request: &String) -> Result<Option<usize>, ureq::Error> {
+        let response = self.head(request).call()?;
+        Ok(response
+            .header("Content-Length")
+            .and_then(|v| v.parse().ok()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_impl::check_range_client;
+
+    #[test]
+    fn range_client() {
+        check_range_client(&ureq::Agent::new(), |url| url);
+    }
+}
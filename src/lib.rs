@@ -1,17 +1,65 @@
 //! 1. Implement [response::MaybePartialResponse] for the response type in your HTTP client library (possibly using a newtype).
 //! 2. Use [request::RangeHeader] to collect [request::HttpRange]s (conveniently constructed from anything implementing [std::ops::RangeBounds]) and convert into the string value for the `Range` header
-//! 3. Send off a request with that header.
-//! 4. Use [response::MaybePartialResponse::sparse_body] to get a [std::io::Read]/[std::io::Seek] representation of the whole remote file. If the response had `Content-Range`s, those ranges will be the fetched data, and the rest will be null bytes.
+//! 3. Send off a request with that header; if updating data fetched earlier, add an `If-Range` header from [request::IfRange].
+//! 4. Use [response::MaybePartialResponse::sparse_body] to get a [std::io::Read]/[std::io::Seek] representation of the whole remote file. If the response had `Content-Range`s, those ranges will be the fetched data, and the rest will be null bytes. [response::SparseBody::get] and [response::SparseBody::get_buf] give the fetched data without copying.
+//! 5. Optionally, add the data from later responses with [response::SparseBody::insert_response], which refuses responses whose validator shows that the representation has changed.
 //!
-//! Assumes that the server has not sent back any overlapping ranges,
-//! and that the returned byteranges have the unit `"bytes"`.
+//! A [client::RangeClient] can send the requests in steps 3 and 5; [client::MockClient] serves a buffer in memory for tests.
+//! Alternatively, [remote::RemoteFile] does all of this lazily behind [std::io::Read]/[std::io::Seek],
+//! given a [remote::RangeTransport] such as [client::ClientTransport] to send the requests.
+//!
+//! With the `reqwest`, `ureq`, `attohttpc` or `http` features, [response::MaybePartialResponse] is implemented for those libraries' responses,
+//! and [client::RangeClient] for their blocking clients (`reqwest::blocking::Client`, `ureq::Agent` and `attohttpc::Session`).
+//!
+//! With the `async` feature, [async_response::AsyncMaybePartialResponse] does the same for clients whose response bodies must be awaited.
+//! With the `http-body` feature, it is implemented for http 1.x responses with any [http_body::Body], such as hyper's,
+//! and [streaming::BodyParts] receives the parts as the body's frames arrive.
+//!
+//! On the serving side, [request::RangeHeader::parse] and [request::RangeHeader::resolve] turn a `Range` header into byte spans,
+//! and [multipart::MultipartBuilder] produces a `multipart/byteranges` body.
+//! [response::Representation] does both to answer requests for data in memory,
+//! and with the `http-body` feature, [response::RangeResponder] does so for a hyper service.
+//!
+//! With the `test-server` feature, [test_server::TestServer] serves a buffer or file over a local port with range support,
+//! optionally misbehaving, for testing clients without the network.
+//!
+//! Assumes that the returned byteranges have the unit `"bytes"`.
+//! Where ranges from different responses overlap, the data received first is kept;
+//! within one response, parts which start earlier or are longer take precedence (see [response::SparseBody::extend_parts]).
 
 pub mod request;
 
 pub mod response;
 
+pub mod multipart;
+
+pub mod streaming;
+
+pub mod remote;
+
+pub mod client;
+
+#[cfg(feature = "test-server")]
+pub mod test_server;
+
+#[cfg(feature = "async")]
+pub mod async_response;
+
+mod media_type;
+
 mod impls;
-pub use impls::*;
+#[cfg(feature = "attohttpc")]
+pub use impls::attohttpc;
+#[cfg(feature = "http")]
+pub use impls::http;
+#[cfg(feature = "reqwest")]
+pub use impls::reqwest;
+#[cfg(test)]
+pub(crate) use impls::test_impl;
+#[cfg(feature = "ureq")]
+pub use impls::ureq;
+#[cfg(feature = "http-body")]
+pub use impls::{http1, http_body};
 
 /// variant_from_data!(EnumType, VariantName, DataType)
 ///
@@ -0,0 +1,161 @@
+use std::borrow::Cow;
+
+use thiserror::Error;
+
+use crate::request::is_tchar;
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("Could not parse media type {0:?}")]
+pub(crate) struct MediaTypeParseError(pub String);
+
+/// A parsed `Content-Type` value, as specified in
+/// [RFC 9110 §8.3.1](https://www.rfc-editor.org/rfc/rfc9110#section-8.3.1).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct MediaType<'a> {
+    /// `type/subtype`, as given.
+    pub essence: &'a str,
+    /// Parameter names and (unquoted) values, in order.
+    pub params: Vec<(&'a str, Cow<'a, str>)>,
+}
+
+impl<'a> MediaType<'a> {
+    pub fn parse(s: &'a str) -> Result<Self, MediaTypeParseError> {
+        let err = || MediaTypeParseError(s.to_owned());
+        let s = s.trim_matches(is_ows);
+        let essence_len = s.find(|c: char| c == ';' || is_ows(c)).unwrap_or(s.len());
+        let essence = &s[..essence_len];
+        let (ty, subtype) = essence.split_once('/').ok_or_else(err)?;
+        if !is_token(ty) || !is_token(subtype) {
+            return Err(err());
+        }
+
+        let mut params = Vec::default();
+        let mut rest = s[essence_len..].trim_start_matches(is_ows);
+        while !rest.is_empty() {
+            rest = rest.strip_prefix(';').ok_or_else(err)?;
+            rest = rest.trim_start_matches(is_ows);
+            // parameters may be empty, e.g. `text/plain;;charset=utf-8`
+            if rest.is_empty() || rest.starts_with(';') {
+                continue;
+            }
+            let (name, after_name) = rest.split_once('=').ok_or_else(err)?;
+            let name = name.trim_end_matches(is_ows);
+            if !is_token(name) {
+                return Err(err());
+            }
+            let after_name = after_name.trim_start_matches(is_ows);
+            let (value, after_value) = if let Some(quoted) = after_name.strip_prefix('"') {
+                parse_quoted(quoted).ok_or_else(err)?
+            } else {
+                let len = after_name
+                    .find(|c: char| c == ';' || is_ows(c))
+                    .unwrap_or(after_name.len());
+                let value = &after_name[..len];
+                if !is_token(value) {
+                    return Err(err());
+                }
+                (Cow::Borrowed(value), &after_name[len..])
+            };
+            params.push((name, value));
+            rest = after_value.trim_start_matches(is_ows);
+        }
+        Ok(Self { essence, params })
+    }
+
+    /// Whether the essence matches the given `type/subtype`, case-insensitively.
+    pub fn is(&self, essence: &str) -> bool {
+        self.essence.eq_ignore_ascii_case(essence)
+    }
+
+    /// The value of the first parameter with the given name, case-insensitively.
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_ref())
+    }
+}
+
+fn is_ows(c: char) -> bool {
+    c == ' ' || c == '\t'
+}
+
+fn is_token(s: &str) -> bool {
+    !s.is_empty() && s.bytes().all(is_tchar)
+}
+
+/// Parse the remainder of a `quoted-string` after the opening quote,
+/// returning the unescaped value and the remainder after the closing quote.
+fn parse_quoted(s: &str) -> Option<(Cow<'_, str>, &str)> {
+    let mut value: Option<String> = None;
+    let mut chars = s.char_indices();
+    while let Some((idx, c)) = chars.next() {
+        match c {
+            '"' => {
+                let value = value.map_or(Cow::Borrowed(&s[..idx]), Cow::Owned);
+                return Some((value, &s[idx + 1..]));
+            }
+            '\\' => {
+                let (_, escaped) = chars.next()?;
+                if escaped.is_ascii_control() && escaped != '\t' {
+                    return None;
+                }
+                value
+                    .get_or_insert_with(|| s[..idx].to_owned())
+                    .push(escaped);
+            }
+            c if c.is_ascii_control() && c != '\t' => return None,
+            c => {
+                if let Some(v) = value.as_mut() {
+                    v.push(c)
+                }
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn simple() {
+        let mt = MediaType::parse("text/plain").unwrap();
+        assert!(mt.is("TEXT/Plain"));
+        assert!(mt.params.is_empty());
+    }
+
+    #[test]
+    fn params() {
+        let mt =
+            MediaType::parse(" multipart/byteranges ;charset=x; BOUNDARY = \"a \\\"b\\\\\" ;; ")
+                .unwrap();
+        assert!(mt.is("multipart/byteranges"));
+        assert_eq!(mt.param("charset"), Some("x"));
+        assert_eq!(mt.param("boundary"), Some("a \"b\\"));
+    }
+
+    #[test]
+    fn first_param_wins() {
+        let mt = MediaType::parse("multipart/byteranges; boundary=a; boundary=b").unwrap();
+        assert_eq!(mt.param("boundary"), Some("a"));
+    }
+
+    #[test]
+    fn invalid() {
+        for s in [
+            "",
+            "text",
+            "text/",
+            "text/plain boundary=x",
+            "text/plain; boundary",
+            "text/plain; boundary=\"unterminated",
+            "text/plain; boundary=a b",
+            "text/plain; boundary=\"x\"y",
+            "te xt/plain",
+        ] {
+            assert!(MediaType::parse(s).is_err(), "{s:?}");
+        }
+    }
+}
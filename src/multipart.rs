@@ -0,0 +1,387 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    io::{self, Write},
+};
+
+use bytes::{BufMut, Bytes, BytesMut};
+use thiserror::Error;
+
+use crate::{media_type::MediaType, request::is_tchar, response::BYTERANGES};
+
+const MAX_BOUNDARY_LEN: usize = 70;
+
+#[derive(Debug, Error)]
+pub enum MultipartError {
+    #[error("Invalid multipart boundary {0:?}")]
+    InvalidBoundary(String),
+    #[error("Invalid part Content-Type {0:?}")]
+    InvalidContentType(String),
+    #[error("Multipart boundary {0:?} occurs in the body")]
+    BoundaryInBody(String),
+    #[error("A multipart/byteranges body needs at least one part")]
+    NoParts,
+    #[error("Part at offset {0} is empty")]
+    EmptyPart(usize),
+    #[error("Part at offset {offset} with length {len} exceeds complete length {complete_length}")]
+    OutOfBounds {
+        offset: usize,
+        len: usize,
+        complete_length: usize,
+    },
+}
+
+/// Whether the byte is a `bchars` as defined in
+/// [RFC 2046 §5.1.1](https://www.rfc-editor.org/rfc/rfc2046#section-5.1.1).
+fn is_bchar(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"'()+_,-./:=? ".contains(&b)
+}
+
+pub(crate) fn validate_boundary(boundary: &str) -> Result<(), MultipartError> {
+    if boundary.is_empty()
+        || boundary.len() > MAX_BOUNDARY_LEN
+        || boundary.ends_with(' ')
+        || !boundary.bytes().all(is_bchar)
+    {
+        return Err(MultipartError::InvalidBoundary(boundary.to_owned()));
+    }
+    Ok(())
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.windows(needle.len()).any(|w| w == needle)
+}
+
+/// Builder for a `multipart/byteranges` response body,
+/// as specified in [RFC 9110 §14.6](https://www.rfc-editor.org/rfc/rfc9110#section-14.6).
+///
+/// The result can be read back with [Parts](crate::response::Parts).
+///
+/// ```rust
+/// # use byteranges::multipart::MultipartBuilder;
+/// # use byteranges::response::Bytes;
+/// let body = MultipartBuilder::new("text/plain")
+///     .complete_length(1000)
+///     .push(0, Bytes::from_static(b"first"))
+///     .push(500, Bytes::from_static(b"middle"))
+///     .build()
+///     .unwrap();
+/// assert!(body.content_type().starts_with("multipart/byteranges; boundary="));
+/// assert_eq!(body.content_length(), body.to_bytes().len());
+/// ```
+#[derive(Debug, Clone)]
+pub struct MultipartBuilder {
+    boundary: Option<String>,
+    content_type: String,
+    complete_length: Option<usize>,
+    parts: Vec<(usize, Bytes)>,
+}
+
+impl MultipartBuilder {
+    /// Create a new builder whose parts have the given `Content-Type`.
+    ///
+    /// It must be a valid media type, which is checked by [MultipartBuilder::build].
+    pub fn new<S: Into<String>>(content_type: S) -> Self {
+        Self {
+            boundary: None,
+            content_type: content_type.into(),
+            complete_length: None,
+            parts: Vec::default(),
+        }
+    }
+
+    /// Use the given boundary rather than generating one.
+    ///
+    /// It must be valid according to RFC 2046 and must not occur in the body.
+    pub fn boundary<S: Into<String>>(&mut self, boundary: S) -> &mut Self {
+        self.boundary = Some(boundary.into());
+        self
+    }
+
+    /// Set the complete length of the representation the parts were taken from.
+    ///
+    /// If not set, the `Content-Range`s will report it as unknown (`*`).
+    pub fn complete_length(&mut self, complete_length: usize) -> &mut Self {
+        self.complete_length = Some(complete_length);
+        self
+    }
+
+    /// Add a part containing the given data, starting at the given offset in the representation.
+    pub fn push(&mut self, offset: usize, data: Bytes) -> &mut Self {
+        self.parts.push((offset, data));
+        self
+    }
+
+    /// Add a number of new parts.
+    pub fn extend<I: IntoIterator<Item = (usize, Bytes)>>(&mut self, parts: I) -> &mut Self {
+        self.parts.extend(parts);
+        self
+    }
+
+    fn generate_boundary(&self) -> String {
+        let state = RandomState::new();
+        let mut count: u64 = 0;
+        loop {
+            let mut hasher = state.build_hasher();
+            hasher.write_u64(count);
+            let boundary = format!("byteranges-{:016x}", hasher.finish());
+            if self.check_boundary(&boundary).is_ok() {
+                return boundary;
+            }
+            count += 1;
+        }
+    }
+
+    fn check_boundary(&self, boundary: &str) -> Result<(), MultipartError> {
+        validate_boundary(boundary)?;
+        let delimiter = format!("--{boundary}");
+        let delimiter = delimiter.as_bytes();
+        if contains(self.content_type.as_bytes(), delimiter)
+            || self.parts.iter().any(|(_, d)| contains(d, delimiter))
+        {
+            return Err(MultipartError::BoundaryInBody(boundary.to_owned()));
+        }
+        Ok(())
+    }
+
+    /// Check the parts and produce the body.
+    pub fn build(&self) -> Result<MultipartBody, MultipartError> {
+        // written into each part's headers, so must not be able to break out of them
+        if MediaType::parse(&self.content_type).is_err() {
+            return Err(MultipartError::InvalidContentType(
+                self.content_type.clone(),
+            ));
+        }
+        if self.parts.is_empty() {
+            return Err(MultipartError::NoParts);
+        }
+        for (offset, data) in self.parts.iter() {
+            if data.is_empty() {
+                return Err(MultipartError::EmptyPart(*offset));
+            }
+            // without a complete length, the part must still end at a representable offset
+            let complete_length = self.complete_length.unwrap_or(usize::MAX);
+            if offset
+                .checked_add(data.len())
+                .is_none_or(|end| end > complete_length)
+            {
+                return Err(MultipartError::OutOfBounds {
+                    offset: *offset,
+                    len: data.len(),
+                    complete_length,
+                });
+            }
+        }
+        let boundary = match &self.boundary {
+            Some(b) => {
+                self.check_boundary(b)?;
+                b.clone()
+            }
+            None => self.generate_boundary(),
+        };
+
+        let complete_length = self
+            .complete_length
+            .map_or_else(|| "*".to_owned(), |l| l.to_string());
+        let parts = self
+            .parts
+            .iter()
+            .map(|(offset, data)| {
+                let head = format!(
+                    "--{boundary}\r\nContent-Type: {}\r\nContent-Range: bytes {offset}-{}/{complete_length}\r\n\r\n",
+                    self.content_type,
+                    offset + data.len() - 1,
+                );
+                (Bytes::from(head), data.clone())
+            })
+            .collect();
+        let tail = Bytes::from(format!("--{boundary}--\r\n"));
+        Ok(MultipartBody {
+            boundary,
+            parts,
+            tail,
+        })
+    }
+}
+
+/// A `multipart/byteranges` body produced by a [MultipartBuilder].
+///
+/// The body can be written out with [MultipartBody::write_to] without copying the part data,
+/// or collected with [MultipartBody::to_bytes].
+#[derive(Debug, Clone)]
+pub struct MultipartBody {
+    boundary: String,
+    /// Delimiter and headers of each part, and its data.
+    parts: Vec<(Bytes, Bytes)>,
+    tail: Bytes,
+}
+
+impl MultipartBody {
+    pub fn boundary(&self) -> &str {
+        &self.boundary
+    }
+
+    /// Value for the response's `Content-Type` header.
+    pub fn content_type(&self) -> String {
+        if self.boundary.bytes().all(is_tchar) {
+            format!("{BYTERANGES}; boundary={}", self.boundary)
+        } else {
+            format!("{BYTERANGES}; boundary=\"{}\"", self.boundary)
+        }
+    }
+
+    /// Value for the response's `Content-Length` header.
+    pub fn content_length(&self) -> usize {
+        self.parts
+            .iter()
+            // +2 for the CRLF after the data
+            .map(|(head, data)| head.len() + data.len() + 2)
+            .sum::<usize>()
+            + self.tail.len()
+    }
+
+    /// Write the whole body.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        for (head, data) in self.parts.iter() {
+            writer.write_all(head)?;
+            writer.write_all(data)?;
+            writer.write_all(b"\r\n")?;
+        }
+        writer.write_all(&self.tail)
+    }
+
+    /// Collect the whole body into a single buffer.
+    pub fn to_bytes(&self) -> Bytes {
+        let mut buf = BytesMut::with_capacity(self.content_length()).writer();
+        self.write_to(&mut buf)
+            .expect("writing to a buffer cannot fail");
+        buf.into_inner().freeze()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::response::{PartDesc, Parts, ResponsePart};
+    use crate::test_impl::read_text;
+
+    fn parse(body: &MultipartBody) -> Vec<ResponsePart> {
+        let part_desc = PartDesc::Multi {
+            boundary: format!("--{}", body.boundary()).into_bytes(),
+        };
+        Parts::new(part_desc, body.to_bytes())
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn roundtrip() {
+        let text = Bytes::from(read_text());
+        let spans = [(0, 50), (1000, 1), (4000, 57)];
+        let body = MultipartBuilder::new("text/plain")
+            .complete_length(text.len())
+            .extend(spans.map(|(o, l)| (o, text.slice(o..o + l))))
+            .build()
+            .unwrap();
+        let parts = parse(&body);
+        assert_eq!(parts.len(), spans.len());
+        for ((offset, len), part) in spans.into_iter().zip(parts) {
+            assert_eq!(part.content_type(), "text/plain");
+            assert_eq!(part.offset_len(), Some((offset, len)));
+            assert_eq!(part.total_size(), Some(text.len()));
+            assert_eq!(part.data(), &text[offset..offset + len]);
+        }
+    }
+
+    #[test]
+    fn content_length_matches() {
+        let body = MultipartBuilder::new("application/octet-stream")
+            .push(10, Bytes::from_static(b"\r\n--\r\n"))
+            .build()
+            .unwrap();
+        let bytes = body.to_bytes();
+        assert_eq!(body.content_length(), bytes.len());
+        assert!(bytes.ends_with(format!("--{}--\r\n", body.boundary()).as_bytes()));
+    }
+
+    #[test]
+    fn quoted_boundary() {
+        let body = MultipartBuilder::new("text/plain")
+            .boundary("has space")
+            .push(0, Bytes::from_static(b"data"))
+            .build()
+            .unwrap();
+        assert_eq!(
+            body.content_type(),
+            "multipart/byteranges; boundary=\"has space\""
+        );
+        assert_eq!(parse(&body)[0].data(), &b"data"[..]);
+    }
+
+    #[test]
+    fn boundary_in_data() {
+        let result = MultipartBuilder::new("text/plain")
+            .boundary("abc")
+            .push(0, Bytes::from_static(b"xx--abcxx"))
+            .build();
+        assert!(matches!(result, Err(MultipartError::BoundaryInBody(_))));
+    }
+
+    #[test]
+    fn invalid_boundary() {
+        for b in ["", "trailing ", "semi;colon", &"a".repeat(71)] {
+            let result = MultipartBuilder::new("text/plain")
+                .boundary(b)
+                .push(0, Bytes::from_static(b"data"))
+                .build();
+            assert!(matches!(result, Err(MultipartError::InvalidBoundary(_))));
+        }
+    }
+
+    #[test]
+    fn invalid_content_type() {
+        for ct in [
+            "",
+            "text",
+            "text/plain\r\nX-Injected: 1",
+            "text/plain; a=\"\r\n\"",
+        ] {
+            let result = MultipartBuilder::new(ct)
+                .push(0, Bytes::from_static(b"data"))
+                .build();
+            assert!(matches!(result, Err(MultipartError::InvalidContentType(_))));
+        }
+    }
+
+    #[test]
+    fn out_of_bounds() {
+        let result = MultipartBuilder::new("text/plain")
+            .complete_length(5)
+            .push(2, Bytes::from_static(b"data"))
+            .build();
+        assert!(matches!(result, Err(MultipartError::OutOfBounds { .. })));
+    }
+
+    #[test]
+    fn offset_overflow() {
+        for complete_length in [None, Some(usize::MAX)] {
+            let mut builder = MultipartBuilder::new("text/plain");
+            builder.push(usize::MAX - 1, Bytes::from_static(b"data"));
+            if let Some(l) = complete_length {
+                builder.complete_length(l);
+            }
+            let result = builder.build();
+            assert!(
+                matches!(
+                    result,
+                    Err(MultipartError::OutOfBounds {
+                        offset,
+                        complete_length: usize::MAX,
+                        ..
+                    }) if offset == usize::MAX - 1
+                ),
+                "{complete_length:?}"
+            );
+        }
+    }
+}
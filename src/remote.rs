@@ -0,0 +1,581 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    io::{self, BufRead, Read, Seek, SeekFrom},
+};
+
+use rope_rd::util::abs_position;
+use thiserror::Error;
+
+use crate::request::{HttpRange, IfRange, RangeHeader, Validator};
+use crate::response::{unsatisfied_length, Bytes, MaybePartialResponse, Source};
+
+pub const DEFAULT_BLOCK_SIZE: usize = 64 * 1024;
+pub const DEFAULT_READ_AHEAD: usize = 3;
+pub const DEFAULT_CACHE_CAPACITY: usize = 64;
+
+/// Something which can send range requests for a single remote file, e.g. an HTTP client and a URL.
+pub trait RangeTransport {
+    type Response: MaybePartialResponse;
+    /// Error from sending a request, e.g. if the connection failed.
+    type Error: Error + Send + Sync + 'static;
+
+    /// Send a `GET` request with the given `Range` header, and `If-Range` header if given.
+    fn get(
+        &mut self,
+        range: &RangeHeader,
+        if_range: Option<&IfRange>,
+    ) -> Result<Self::Response, Self::Error>;
+
+    /// The length of the file if it can be found cheaply, e.g. from the `Content-Length` of a `HEAD` request.
+    ///
+    /// By default [None], in which case the length is found with a suffix range request.
+    fn content_length(&mut self) -> Result<Option<usize>, Self::Error> {
+        Ok(None)
+    }
+}
+
+/// Error from reading a [RemoteFile].
+///
+/// Returned as the inner error of an [io::Error];
+/// use [RemoteFileError::from_io] to get it back.
+#[derive(Debug, Error)]
+pub enum RemoteFileError {
+    #[error("Request failed: {0}")]
+    Transport(Box<dyn Error + Send + Sync>),
+    #[error("Unexpected response status {0}")]
+    Status(u16),
+    #[error("Could not read response: {0}")]
+    Response(Box<dyn Error + Send + Sync>),
+    #[error("Response did not contain byte {0}")]
+    Missing(usize),
+    #[error("Could not find the length of the remote file")]
+    UnknownLength,
+    #[error("Complete length {found} conflicts with previously reported {expected}")]
+    LengthMismatch { expected: usize, found: usize },
+    #[error("Validator {found} does not match {expected}: the remote file has changed")]
+    ValidatorMismatch {
+        expected: Validator,
+        found: Validator,
+    },
+}
+
+impl RemoteFileError {
+    /// Get the [RemoteFileError] from an [io::Error] returned by a [RemoteFile], if that was the cause.
+    pub fn from_io(err: &io::Error) -> Option<&Self> {
+        err.get_ref().and_then(|e| e.downcast_ref())
+    }
+}
+
+impl From<RemoteFileError> for io::Error {
+    fn from(value: RemoteFileError) -> Self {
+        let kind = match value {
+            RemoteFileError::Response(_) | RemoteFileError::Missing(_) => {
+                io::ErrorKind::InvalidData
+            }
+            _ => io::ErrorKind::Other,
+        };
+        io::Error::new(kind, value)
+    }
+}
+
+fn transport_error<E: Error + Send + Sync + 'static>(e: E) -> RemoteFileError {
+    RemoteFileError::Transport(Box::new(e))
+}
+
+fn response_error<E: Error + Send + Sync + 'static>(e: E) -> RemoteFileError {
+    RemoteFileError::Response(Box::new(e))
+}
+
+/// Least-recently-used cache of blocks by index.
+#[derive(Debug)]
+struct BlockCache {
+    capacity: usize,
+    /// Block data and when it was last used.
+    blocks: HashMap<usize, (Bytes, u64)>,
+    tick: u64,
+}
+
+impl BlockCache {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            blocks: HashMap::default(),
+            tick: 0,
+        }
+    }
+
+    fn get(&mut self, idx: usize) -> Option<Bytes> {
+        self.tick += 1;
+        let (data, used) = self.blocks.get_mut(&idx)?;
+        *used = self.tick;
+        Some(data.clone())
+    }
+
+    fn contains(&self, idx: usize) -> bool {
+        self.blocks.contains_key(&idx)
+    }
+
+    fn insert(&mut self, idx: usize, data: Bytes) {
+        self.tick += 1;
+        while self.blocks.len() >= self.capacity && !self.blocks.contains_key(&idx) {
+            let Some(oldest) = self
+                .blocks
+                .iter()
+                .min_by_key(|(_, (_, used))| *used)
+                .map(|(i, _)| *i)
+            else {
+                break;
+            };
+            self.blocks.remove(&oldest);
+        }
+        self.blocks.insert(idx, (data, self.tick));
+    }
+
+    fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.blocks.len() > capacity {
+            let oldest = self
+                .blocks
+                .iter()
+                .min_by_key(|(_, (_, used))| *used)
+                .map(|(i, _)| *i)
+                .expect("cache is not empty");
+            self.blocks.remove(&oldest);
+        }
+    }
+
+    fn clear(&mut self) {
+        self.blocks.clear();
+    }
+}
+
+/// A remote file with [Read], [BufRead] and [Seek], which fetches blocks with range requests as they are read.
+///
+/// Each request fetches the block containing the cursor,
+/// and up to [RemoteFile::set_read_ahead] following blocks which are not already cached.
+/// Recently used blocks are kept in a least-recently-used cache.
+/// If the server ignores a range and sends the whole file, it is kept and no more requests are sent.
+///
+/// The length of the file is found when it is first needed,
+/// from [RangeTransport::content_length] or with a suffix range request.
+/// Once a response has reported an `ETag` or `Last-Modified`, it is sent as `If-Range` with later requests,
+/// and a [RemoteFileError::ValidatorMismatch] is returned if the file changes.
+pub struct RemoteFile<T: RangeTransport> {
+    transport: T,
+    position: u64,
+    complete_length: Option<usize>,
+    block_size: usize,
+    read_ahead: usize,
+    cache: BlockCache,
+    /// The block under the cursor, to be returned by [BufRead::fill_buf].
+    current: Option<(usize, Bytes)>,
+    /// The whole file, if a response to a range request contained it.
+    whole: Option<Bytes>,
+    source: Option<Source>,
+    if_range: Option<IfRange>,
+}
+
+impl<T: RangeTransport> RemoteFile<T> {
+    pub fn new(transport: T) -> Self {
+        Self {
+            transport,
+            position: 0,
+            complete_length: None,
+            block_size: DEFAULT_BLOCK_SIZE,
+            read_ahead: DEFAULT_READ_AHEAD,
+            cache: BlockCache::new(DEFAULT_CACHE_CAPACITY),
+            current: None,
+            whole: None,
+            source: None,
+            if_range: None,
+        }
+    }
+
+    /// Set the number of bytes fetched at a time (default [DEFAULT_BLOCK_SIZE]); this clears the cache.
+    ///
+    /// Panics if the block size is 0.
+    pub fn set_block_size(&mut self, block_size: usize) -> &mut Self {
+        assert!(block_size > 0, "block size must be positive");
+        self.block_size = block_size;
+        self.cache.clear();
+        self.current = None;
+        self
+    }
+
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    /// Set the number of blocks to fetch after the one being read (default [DEFAULT_READ_AHEAD]).
+    pub fn set_read_ahead(&mut self, blocks: usize) -> &mut Self {
+        self.read_ahead = blocks;
+        self
+    }
+
+    pub fn read_ahead(&self) -> usize {
+        self.read_ahead
+    }
+
+    /// Set the number of blocks to keep in the cache (default [DEFAULT_CACHE_CAPACITY]).
+    ///
+    /// Panics if the capacity is 0.
+    pub fn set_cache_capacity(&mut self, blocks: usize) -> &mut Self {
+        assert!(blocks > 0, "cache capacity must be positive");
+        self.cache.set_capacity(blocks);
+        self
+    }
+
+    pub fn cache_capacity(&self) -> usize {
+        self.cache.capacity
+    }
+
+    /// The length of the remote file, sending a request to find it if necessary.
+    pub fn complete_length(&mut self) -> io::Result<usize> {
+        Ok(self.discover_length()?)
+    }
+
+    /// The validator of the remote file, once a response has reported one.
+    pub fn validator(&self) -> Option<Validator> {
+        self.source.as_ref().and_then(Source::validator)
+    }
+
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+
+    pub fn into_transport(self) -> T {
+        self.transport
+    }
+
+    fn discover_length(&mut self) -> Result<usize, RemoteFileError> {
+        if let Some(len) = self.complete_length {
+            return Ok(len);
+        }
+        if let Some(len) = self.transport.content_length().map_err(transport_error)? {
+            self.complete_length = Some(len);
+            return Ok(len);
+        }
+        let range = RangeHeader::from(HttpRange::Suffix(1));
+        let response = self.transport.get(&range, None).map_err(transport_error)?;
+        self.check_source(&response)?;
+        let len = match response.status_code() {
+            200 => {
+                let body = response.body().map_err(response_error)?;
+                let len = body.len();
+                self.whole = Some(body);
+                len
+            }
+            206 => response
+                .parts()
+                .map_err(response_error)?
+                .next()
+                .ok_or(RemoteFileError::UnknownLength)?
+                .map_err(response_error)?
+                .total_size()
+                .ok_or(RemoteFileError::UnknownLength)?,
+            // only an empty file cannot satisfy a suffix range
+            416 => unsatisfied_length(response.content_range_str())
+                .ok_or(RemoteFileError::UnknownLength)?,
+            n => return Err(RemoteFileError::Status(n)),
+        };
+        self.complete_length = Some(len);
+        Ok(len)
+    }
+
+    /// Compare the response's validators with those of earlier responses, and adopt them if there were none.
+    fn check_source<R: MaybePartialResponse>(
+        &mut self,
+        response: &R,
+    ) -> Result<(), RemoteFileError> {
+        let source = Source::from_response(response);
+        if let Some(existing) = &self.source {
+            if let Some((expected, found)) = existing.conflict(&source) {
+                return Err(RemoteFileError::ValidatorMismatch { expected, found });
+            }
+            if existing.validator().is_some() {
+                return Ok(());
+            }
+        }
+        self.if_range = source
+            .etag
+            .clone()
+            .and_then(|e| IfRange::new(e).ok())
+            .or_else(|| source.last_modified.and_then(|t| IfRange::new(t).ok()));
+        self.source = Some(source);
+        Ok(())
+    }
+
+    fn check_length(&self, found: usize) -> Result<(), RemoteFileError> {
+        match self.complete_length {
+            Some(expected) if expected != found => {
+                Err(RemoteFileError::LengthMismatch { expected, found })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Cache the blocks between `first` and `last` (inclusive) which are wholly contained in the data,
+    /// returning the first block if it was.
+    fn insert_blocks(
+        &mut self,
+        offset: usize,
+        data: &Bytes,
+        first: usize,
+        last: usize,
+    ) -> Option<Bytes> {
+        let len = self.complete_length.unwrap_or(0);
+        let mut found = None;
+        // insert the first block last, so that it is not evicted by the others
+        for idx in (first..=last).rev() {
+            let start = idx * self.block_size;
+            let end = (start + self.block_size).min(len);
+            if start < offset || end > offset + data.len() || start >= end {
+                continue;
+            }
+            let block = data.slice(start - offset..end - offset);
+            if idx == first {
+                found = Some(block.clone());
+            }
+            self.cache.insert(idx, block);
+        }
+        found
+    }
+
+    /// Fetch the given block and those following it, up to the read-ahead.
+    fn fetch(&mut self, idx: usize) -> Result<Bytes, RemoteFileError> {
+        let len = self.discover_length()?;
+        let n_blocks = len.div_ceil(self.block_size);
+        let mut last = idx;
+        while last + 1 < n_blocks && last - idx < self.read_ahead && !self.cache.contains(last + 1)
+        {
+            last += 1;
+        }
+        let start = idx * self.block_size;
+        let end = ((last + 1) * self.block_size).min(len);
+        let range = RangeHeader::from(start as u64..end as u64);
+
+        let response = self
+            .transport
+            .get(&range, self.if_range.as_ref())
+            .map_err(transport_error)?;
+        self.check_source(&response)?;
+        let mut found = None;
+        match response.status_code() {
+            // the server ignored the range, so keep the whole file rather than fetching it again
+            200 => {
+                let body = response.body().map_err(response_error)?;
+                self.check_length(body.len())?;
+                self.whole = Some(body);
+                return self.block(idx);
+            }
+            206 => {
+                for part in response.parts().map_err(response_error)? {
+                    let part = part.map_err(response_error)?;
+                    if let Some(total) = part.total_size() {
+                        self.check_length(total)?;
+                    }
+                    if let Some((offset, _)) = part.offset_len() {
+                        found = found.or(self.insert_blocks(offset, part.data(), idx, last));
+                    }
+                }
+            }
+            416 => {
+                return Err(match unsatisfied_length(response.content_range_str()) {
+                    Some(found) => RemoteFileError::LengthMismatch {
+                        expected: len,
+                        found,
+                    },
+                    None => RemoteFileError::Status(416),
+                })
+            }
+            n => return Err(RemoteFileError::Status(n)),
+        }
+        found.ok_or(RemoteFileError::Missing(start))
+    }
+
+    fn block(&mut self, idx: usize) -> Result<Bytes, RemoteFileError> {
+        if let Some(whole) = &self.whole {
+            let start = idx * self.block_size;
+            let end = (start + self.block_size).min(whole.len());
+            if start >= end {
+                return Err(RemoteFileError::Missing(start));
+            }
+            return Ok(whole.slice(start..end));
+        }
+        match self.cache.get(idx) {
+            Some(block) => Ok(block),
+            None => self.fetch(idx),
+        }
+    }
+}
+
+impl<T: RangeTransport> BufRead for RemoteFile<T> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        let len = self.discover_length()?;
+        let Ok(position) = usize::try_from(self.position) else {
+            return Ok(&[]);
+        };
+        if position >= len {
+            return Ok(&[]);
+        }
+        let idx = position / self.block_size;
+        if !matches!(self.current, Some((i, _)) if i == idx) {
+            self.current = Some((idx, self.block(idx)?));
+        }
+        let (_, block) = self.current.as_ref().expect("current block was just set");
+        Ok(&block[position - idx * self.block_size..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.position += amt as u64;
+    }
+}
+
+impl<T: RangeTransport> Read for RemoteFile<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<T: RangeTransport> Seek for RemoteFile<T> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let len = match pos {
+            SeekFrom::End(_) => self.discover_length()?,
+            _ => 0,
+        };
+        self.position = abs_position(self.position, len as u64, pos)?;
+        Ok(self.position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::{ClientTransport, MockClient};
+    use crate::request::EntityTag;
+    use crate::test_impl::{lorem, read_text};
+
+    type MockFile = RemoteFile<ClientTransport<MockClient>>;
+
+    fn remote_file(data: Vec<u8>, head: bool) -> MockFile {
+        let mut representation = lorem();
+        representation.set_data(Bytes::from(data));
+        let mut client = MockClient::from(representation);
+        client.set_head(head);
+        let mut file = RemoteFile::new(ClientTransport::new(client, ()));
+        file.set_block_size(100)
+            .set_read_ahead(2)
+            .set_cache_capacity(4);
+        file
+    }
+
+    fn requests(file: &MockFile) -> Vec<String> {
+        file.transport().client.requests()
+    }
+
+    #[test]
+    fn reads_whole_file() {
+        let text = read_text();
+        let mut file = remote_file(text.clone(), false);
+        let mut buf = Vec::default();
+        file.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, text);
+        let requests = requests(&file);
+        assert_eq!(requests[..3], ["bytes=-1", "bytes=0-299", "bytes=300-599"]);
+        // 4057 bytes in blocks of 100, 3 at a time
+        assert_eq!(requests.len(), 1 + 41usize.div_ceil(3));
+    }
+
+    #[test]
+    fn seeks_and_caches() {
+        let text = read_text();
+        let mut file = remote_file(text.clone(), true);
+        let mut buf = [0; 50];
+        file.seek(SeekFrom::End(-50)).unwrap();
+        file.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, text[4007..]);
+        assert_eq!(file.read(&mut buf).unwrap(), 0);
+
+        file.seek(SeekFrom::Start(1050)).unwrap();
+        file.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, text[1050..1100]);
+        // already fetched as read-ahead
+        file.seek(SeekFrom::Start(1250)).unwrap();
+        file.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, text[1250..1300]);
+        assert_eq!(requests(&file), ["bytes=4000-4056", "bytes=1000-1299"]);
+    }
+
+    #[test]
+    fn buf_read() {
+        let mut file = remote_file(b"one\ntwo\nthree".to_vec(), false);
+        file.set_block_size(5);
+        let lines: Vec<_> = file.lines().collect::<Result<_, _>>().unwrap();
+        assert_eq!(lines, ["one", "two", "three"]);
+    }
+
+    #[test]
+    fn empty_file() {
+        let mut file = remote_file(Vec::default(), false);
+        assert_eq!(file.complete_length().unwrap(), 0);
+        let mut buf = Vec::default();
+        assert_eq!(file.read_to_end(&mut buf).unwrap(), 0);
+    }
+
+    #[test]
+    fn range_ignored() {
+        let text = read_text();
+        // the whole file is returned by the request for its length, or for the first block
+        for (head, first) in [(false, "bytes=-1"), (true, "bytes=2000-2299")] {
+            let mut file = remote_file(text.clone(), head);
+            file.transport.client.set_ignore_range(true);
+            let mut buf = vec![0; 300];
+            file.seek(SeekFrom::Start(2000)).unwrap();
+            file.read_exact(&mut buf).unwrap();
+            assert_eq!(buf, text[2000..2300]);
+            file.rewind().unwrap();
+            let mut buf = Vec::default();
+            file.read_to_end(&mut buf).unwrap();
+            assert_eq!(buf, text);
+            assert_eq!(requests(&file), [first]);
+        }
+    }
+
+    #[test]
+    fn file_changed() {
+        let mut file = remote_file(read_text(), false);
+        let mut buf = [0; 10];
+        file.read_exact(&mut buf).unwrap();
+        assert_eq!(file.validator().unwrap().to_string(), "\"1\"");
+
+        file.transport
+            .client
+            .representation_mut()
+            .set_etag(Some(EntityTag::strong("2").unwrap()));
+        file.seek(SeekFrom::Start(2000)).unwrap();
+        let err = file.read_exact(&mut buf).unwrap_err();
+        assert!(matches!(
+            RemoteFileError::from_io(&err),
+            Some(RemoteFileError::ValidatorMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn cache_evicts_least_recently_used() {
+        let mut cache = BlockCache::new(2);
+        cache.insert(0, Bytes::from_static(b"0"));
+        cache.insert(1, Bytes::from_static(b"1"));
+        cache.get(0);
+        cache.insert(2, Bytes::from_static(b"2"));
+        assert!(cache.contains(0));
+        assert!(!cache.contains(1));
+        cache.set_capacity(1);
+        assert!(cache.contains(2));
+        assert!(!cache.contains(0));
+    }
+}
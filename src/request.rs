@@ -1,7 +1,16 @@
-use std::{fmt::Display, ops::RangeBounds};
+use std::{fmt::Display, ops::RangeBounds, str::FromStr, time::SystemTime};
+
+use httpdate::{fmt_http_date, parse_http_date};
+use thiserror::Error;
+
+use crate::response::ResponsePart;
+
+#[cfg(feature = "http-body")]
+pub use crate::impls::http_body_impl::request_range;
 
 pub const BYTES: &str = "bytes";
 pub const RANGE: &str = "Range";
+pub const IF_RANGE: &str = "If-Range";
 
 /// A single range in a `Range` request.
 ///
@@ -11,6 +20,9 @@ pub const RANGE: &str = "Range";
 /// # use byteranges::request::HttpRange;
 /// let range: HttpRange = (50..150).into();
 /// ```
+///
+/// It can also be parsed from a single `range-spec` like `"50-149"` or `"-100"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum HttpRange {
     /// A range with a given start point and possibly an end point (otherwise EOF).
     Range { start: u64, end: Option<u64> },
@@ -33,6 +45,39 @@ impl Display for HttpRange {
     }
 }
 
+impl HttpRange {
+    /// Resolve this range against a representation of the given complete length,
+    /// as specified in [RFC 9110 §14.1.1](https://www.rfc-editor.org/rfc/rfc9110#section-14.1.1).
+    ///
+    /// Returns the offset and length of the selected bytes,
+    /// in the same form as [ResponsePart::offset_len](crate::response::ResponsePart::offset_len),
+    /// or [None] if the range is not satisfiable.
+    /// Ranges which extend past the end of the representation are clamped to it,
+    /// and suffixes longer than the representation select the whole of it.
+    ///
+    /// ```rust
+    /// # use byteranges::request::HttpRange;
+    /// assert_eq!(HttpRange::from(90..200).resolve(100), Some((90, 10)));
+    /// assert_eq!(HttpRange::Suffix(500).resolve(100), Some((0, 100)));
+    /// assert_eq!(HttpRange::from(100..).resolve(100), None);
+    /// ```
+    pub fn resolve(&self, complete_length: usize) -> Option<(usize, usize)> {
+        let length = complete_length as u64;
+        let (offset, end) = match *self {
+            HttpRange::Range { start, end } => {
+                if start >= length {
+                    return None;
+                }
+                let end = end.map_or(length, |e| e.saturating_add(1).min(length));
+                (start, end)
+            }
+            HttpRange::Suffix(0) => return None,
+            HttpRange::Suffix(len) => (length.saturating_sub(len), length),
+        };
+        Some((offset as usize, (end - offset) as usize))
+    }
+}
+
 impl<T: RangeBounds<u64>> From<T> for HttpRange {
     fn from(value: T) -> Self {
         use std::ops::Bound::*;
@@ -50,6 +95,74 @@ impl<T: RangeBounds<u64>> From<T> for HttpRange {
     }
 }
 
+/// Error parsing a `Range` header value, as specified in
+/// [RFC 9110 §14.1.1](https://www.rfc-editor.org/rfc/rfc9110#section-14.1.1).
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum RangeParseError {
+    #[error("No '=' between range unit and range set")]
+    MissingEquals,
+    #[error("Invalid range unit {0:?}")]
+    InvalidUnit(String),
+    #[error("Range set contains no ranges")]
+    EmptyRangeSet,
+    #[error("Could not parse range {0:?}")]
+    InvalidRange(String),
+    #[error("Range {0:?} does not fit in a u64")]
+    Overflow(String),
+    #[error("Range {0:?} ends before it starts")]
+    EndBeforeStart(String),
+}
+
+fn parse_pos(digits: &str, spec: &str) -> Result<u64, RangeParseError> {
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(RangeParseError::InvalidRange(spec.to_owned()));
+    }
+    // only digits, so the only possible failure is overflow
+    digits
+        .parse()
+        .map_err(|_| RangeParseError::Overflow(spec.to_owned()))
+}
+
+impl FromStr for HttpRange {
+    type Err = RangeParseError;
+
+    /// Parse a single `int-range` or `suffix-range`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((first, last)) = s.split_once('-') else {
+            return Err(RangeParseError::InvalidRange(s.to_owned()));
+        };
+        if first.is_empty() {
+            return Ok(HttpRange::Suffix(parse_pos(last, s)?));
+        }
+        let start = parse_pos(first, s)?;
+        if last.is_empty() {
+            return Ok(HttpRange::Range { start, end: None });
+        }
+        let end = parse_pos(last, s)?;
+        if end < start {
+            return Err(RangeParseError::EndBeforeStart(s.to_owned()));
+        }
+        Ok(HttpRange::Range {
+            start,
+            end: Some(end),
+        })
+    }
+}
+
+/// Whether the byte is a `tchar` as defined in
+/// [RFC 9110 §5.6.2](https://www.rfc-editor.org/rfc/rfc9110#section-5.6.2).
+pub(crate) fn is_tchar(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b)
+}
+
+/// None of the ranges in a [RangeHeader] can be satisfied;
+/// a server should respond with 416 Range Not Satisfiable.
+///
+/// Contains the complete length the ranges were resolved against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+#[error("No range is satisfiable for complete length {0}")]
+pub struct Unsatisfiable(pub usize);
+
 /// Representation of a HTTP `Range` header.
 ///
 /// By default, uses `bytes` units.
@@ -59,6 +172,9 @@ impl<T: RangeBounds<u64>> From<T> for HttpRange {
 /// # use byteranges::request::{HttpRange, RangeHeader};
 /// let header: RangeHeader = [0..50, 125..150].into_iter().collect();
 /// ```
+///
+/// Header values can be parsed back with [RangeHeader::parse].
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct RangeHeader<'a> {
     unit: &'a str,
     ranges: Vec<HttpRange>,
@@ -90,6 +206,179 @@ impl<'a> RangeHeader<'a> {
         self
     }
 
+    /// Parse the value of a `Range` header, e.g. `bytes=0-49,-100,9500-`.
+    ///
+    /// Whitespace around the value and around list elements is ignored, as are empty list elements.
+    /// The unit is matched case-insensitively: `bytes` is normalised to [BYTES],
+    /// and any other unit is kept as given.
+    ///
+    /// ```rust
+    /// # use byteranges::request::{HttpRange, RangeHeader};
+    /// let header = RangeHeader::parse("bytes=0-49, -100").unwrap();
+    /// assert_eq!(header.ranges(), &[(0..50).into(), HttpRange::Suffix(100)]);
+    /// ```
+    pub fn parse(s: &'a str) -> Result<Self, RangeParseError> {
+        let (unit, range_set) = s
+            .trim()
+            .split_once('=')
+            .ok_or(RangeParseError::MissingEquals)?;
+        if unit.is_empty() || !unit.bytes().all(is_tchar) {
+            return Err(RangeParseError::InvalidUnit(unit.to_owned()));
+        }
+        let unit = if unit.eq_ignore_ascii_case(BYTES) {
+            BYTES
+        } else {
+            unit
+        };
+        let ranges = range_set
+            .split(',')
+            .map(|spec| spec.trim_matches([' ', '\t']))
+            .filter(|spec| !spec.is_empty())
+            .map(HttpRange::from_str)
+            .collect::<Result<Vec<_>, _>>()?;
+        if ranges.is_empty() {
+            return Err(RangeParseError::EmptyRangeSet);
+        }
+        Ok(Self { unit, ranges })
+    }
+
+    /// The range unit, e.g. `bytes`.
+    pub fn unit(&self) -> &str {
+        self.unit
+    }
+
+    /// The ranges, in the order they were added.
+    pub fn ranges(&self) -> &[HttpRange] {
+        &self.ranges
+    }
+
+    /// Whether the header contains no ranges, in which case it should not be sent.
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// Resolve every range against a representation of the given complete length (see [HttpRange::resolve]).
+    ///
+    /// Unsatisfiable ranges are dropped; the others are returned as offsets and lengths in request order.
+    /// If no range is satisfiable, the whole request is, and an [Unsatisfiable] error is returned.
+    ///
+    /// The unit is not checked: the ranges are assumed to be bytes.
+    pub fn resolve(&self, complete_length: usize) -> Result<Vec<(usize, usize)>, Unsatisfiable> {
+        let resolved: Vec<_> = self
+            .ranges
+            .iter()
+            .filter_map(|r| r.resolve(complete_length))
+            .collect();
+        if resolved.is_empty() {
+            return Err(Unsatisfiable(complete_length));
+        }
+        Ok(resolved)
+    }
+
+    /// Drop the ranges which are not satisfiable for the given complete length,
+    /// and end the others within it.
+    ///
+    /// Useful for retrying a request against a server which responds 416 Range Not Satisfiable
+    /// to ranges which extend past the end of the representation: see
+    /// [retry_clamped](crate::response::retry_clamped).
+    ///
+    /// ```rust
+    /// # use byteranges::request::RangeHeader;
+    /// let header = RangeHeader::parse("bytes=0-99,4000-4999,5000-,-10").unwrap();
+    /// assert_eq!(header.clamp(4057).unwrap().to_string(), "bytes=0-99,4000-4056,-10");
+    /// ```
+    pub fn clamp(&self, complete_length: usize) -> Result<RangeHeader<'a>, Unsatisfiable> {
+        let last = complete_length.saturating_sub(1) as u64;
+        let ranges: Vec<_> = self
+            .ranges
+            .iter()
+            .filter(|r| r.resolve(complete_length).is_some())
+            .map(|r| match *r {
+                HttpRange::Range { start, end } => HttpRange::Range {
+                    start,
+                    end: end.map(|e| e.min(last)),
+                },
+                suffix => suffix,
+            })
+            .collect();
+        if ranges.is_empty() {
+            return Err(Unsatisfiable(complete_length));
+        }
+        Ok(RangeHeader {
+            unit: self.unit,
+            ranges,
+        })
+    }
+
+    /// Sort the ranges and merge any which overlap, are adjacent, or are separated by fewer than `coalesce_gap` bytes.
+    ///
+    /// If the `complete_length` is known, suffix ranges are converted into ranges from an offset so they can be merged with the others.
+    /// Otherwise, they are merged into the longest suffix, which comes last.
+    ///
+    /// The result keeps track of where each original range ended up: see [NormalizedRanges::locate].
+    ///
+    /// ```rust
+    /// # use byteranges::request::{Location, RangeHeader};
+    /// let header = RangeHeader::parse("bytes=500-700,0-99,601-999,-100").unwrap();
+    /// let normalized = header.normalize(0, Some(1050));
+    /// assert_eq!(normalized.header().to_string(), "bytes=0-99,500-1049");
+    /// assert_eq!(normalized.locate(2), Some((1, Location::Start(101))));
+    /// ```
+    pub fn normalize(
+        &self,
+        coalesce_gap: u64,
+        complete_length: Option<usize>,
+    ) -> NormalizedRanges<'a> {
+        // span, original index
+        let mut spans: Vec<(Span, usize)> = Vec::with_capacity(self.ranges.len());
+        // suffix length, original index
+        let mut suffixes: Vec<(u64, usize)> = Vec::default();
+        for (idx, range) in self.ranges.iter().enumerate() {
+            match (*range, complete_length) {
+                (HttpRange::Range { start, end }, _) => {
+                    spans.push(((start, end.and_then(|e| e.checked_add(1))), idx))
+                }
+                (HttpRange::Suffix(len), Some(complete)) if len > 0 && complete > 0 => {
+                    let complete = complete as u64;
+                    spans.push(((complete.saturating_sub(len), Some(complete)), idx))
+                }
+                (HttpRange::Suffix(len), _) => suffixes.push((len, idx)),
+            }
+        }
+        spans.sort_by_key(|((start, _), _)| *start);
+        let (merged, mapping) = coalesce_spans(
+            &spans.iter().map(|(span, _)| *span).collect::<Vec<_>>(),
+            coalesce_gap,
+            None,
+        );
+
+        let mut locations = vec![(0, Location::Start(0)); self.ranges.len()];
+        for (((start, _), idx), merged_idx) in spans.into_iter().zip(mapping) {
+            locations[idx] = (merged_idx, Location::Start(start - merged[merged_idx].0));
+        }
+        let mut ranges: Vec<_> = merged
+            .into_iter()
+            .map(|(start, end)| HttpRange::Range {
+                start,
+                end: end.map(|e| e - 1),
+            })
+            .collect();
+        if let Some(longest) = suffixes.iter().map(|(len, _)| *len).max() {
+            // where each starts depends on how long the representation turns out to be
+            for (len, idx) in suffixes {
+                locations[idx] = (ranges.len(), Location::End(len));
+            }
+            ranges.push(HttpRange::Suffix(longest));
+        }
+        NormalizedRanges {
+            header: RangeHeader {
+                unit: self.unit,
+                ranges,
+            },
+            locations,
+        }
+    }
+
     pub fn to_header(&self, newline: bool) -> Vec<u8> {
         let s = self.to_string();
         let suffix = if newline { "\r\n" } else { "" };
@@ -112,7 +401,12 @@ impl Default for RangeHeader<'_> {
 
 impl Display for RangeHeader<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let Some(range_string) = self.ranges.iter().map(|r| r.to_string()).reduce(|accum, next| accum + "," + &next) else {
+        let Some(range_string) = self
+            .ranges
+            .iter()
+            .map(|r| r.to_string())
+            .reduce(|accum, next| accum + "," + &next)
+        else {
             return Ok(());
         };
         f.write_fmt(format_args!("{0}={range_string}", self.unit))
@@ -135,9 +429,422 @@ impl<R: Into<HttpRange>> FromIterator<R> for RangeHeader<'static> {
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("Invalid entity tag {0:?}")]
+pub struct InvalidETag(pub String);
+
+/// An entity tag, as used in the `ETag` and `If-Range` headers;
+/// see [RFC 9110 §8.8.3](https://www.rfc-editor.org/rfc/rfc9110#section-8.8.3).
+///
+/// ```rust
+/// # use byteranges::request::EntityTag;
+/// let etag: EntityTag = "W/\"abc\"".parse().unwrap();
+/// assert!(etag.is_weak());
+/// assert_eq!(etag.tag(), "abc");
+/// assert!(!etag.strong_eq(&etag));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct EntityTag {
+    weak: bool,
+    tag: String,
+}
+
+impl EntityTag {
+    /// Create a strong entity tag from its opaque value, without the quotes.
+    pub fn strong<S: Into<String>>(tag: S) -> Result<Self, InvalidETag> {
+        let tag = tag.into();
+        if !tag.bytes().all(is_etagc) {
+            return Err(InvalidETag(tag));
+        }
+        Ok(Self { weak: false, tag })
+    }
+
+    pub fn is_weak(&self) -> bool {
+        self.weak
+    }
+
+    /// The opaque value, without the quotes or weakness indicator.
+    pub fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    /// Whether both tags are strong and identical.
+    pub fn strong_eq(&self, other: &Self) -> bool {
+        !self.weak && !other.weak && self.tag == other.tag
+    }
+}
+
+fn is_etagc(b: u8) -> bool {
+    b == 0x21 || (0x23..=0x7e).contains(&b) || b >= 0x80
+}
+
+impl FromStr for EntityTag {
+    type Err = InvalidETag;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || InvalidETag(s.to_owned());
+        let trimmed = s.trim();
+        let (weak, quoted) = match trimmed.strip_prefix("W/") {
+            Some(q) => (true, q),
+            None => (false, trimmed),
+        };
+        let tag = quoted
+            .strip_prefix('"')
+            .and_then(|q| q.strip_suffix('"'))
+            .ok_or_else(err)?;
+        if !tag.bytes().all(is_etagc) {
+            return Err(err());
+        }
+        Ok(Self {
+            weak,
+            tag: tag.to_owned(),
+        })
+    }
+}
+
+impl Display for EntityTag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.weak {
+            f.write_str("W/")?;
+        }
+        f.write_fmt(format_args!("\"{}\"", self.tag))
+    }
+}
+
+/// A validator identifying a version of a representation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Validator {
+    ETag(EntityTag),
+    /// The `Last-Modified` date, with a resolution of one second.
+    LastModified(SystemTime),
+}
+
+impl Validator {
+    /// Whether the two validators identify the same version of a representation,
+    /// using the strong comparison which `If-Range` requires.
+    ///
+    /// Validators of different kinds never match.
+    pub fn matches(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Validator::ETag(a), Validator::ETag(b)) => a.strong_eq(b),
+            (Validator::LastModified(a), Validator::LastModified(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Display for Validator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Validator::ETag(etag) => etag.fmt(f),
+            Validator::LastModified(t) => f.write_str(&fmt_http_date(*t)),
+        }
+    }
+}
+
+impl From<EntityTag> for Validator {
+    fn from(value: EntityTag) -> Self {
+        Validator::ETag(value)
+    }
+}
+
+impl From<SystemTime> for Validator {
+    fn from(value: SystemTime) -> Self {
+        Validator::LastModified(value)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("If-Range cannot use the weak entity tag {0}")]
+pub struct WeakETag(pub EntityTag);
+
+/// Representation of a HTTP `If-Range` header,
+/// to be sent alongside a `Range` header;
+/// see [RFC 9110 §13.1.5](https://www.rfc-editor.org/rfc/rfc9110#section-13.1.5).
+///
+/// If the representation no longer matches the validator,
+/// the server ignores the `Range` and sends the whole of it in a 200 response.
+/// See [MaybePartialResponse::sparse_body_if_range](crate::response::MaybePartialResponse::sparse_body_if_range).
+///
+/// ```rust
+/// # use byteranges::request::{EntityTag, IfRange};
+/// let etag: EntityTag = "\"abc\"".parse().unwrap();
+/// let if_range = IfRange::new(etag).unwrap();
+/// assert_eq!(if_range.to_string(), "\"abc\"");
+/// assert!(IfRange::new("W/\"abc\"".parse::<EntityTag>().unwrap()).is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IfRange(Validator);
+
+impl IfRange {
+    /// Entity tags must be strong.
+    pub fn new<V: Into<Validator>>(validator: V) -> Result<Self, WeakETag> {
+        match validator.into() {
+            Validator::ETag(etag) if etag.is_weak() => Err(WeakETag(etag)),
+            v => Ok(Self(v)),
+        }
+    }
+
+    /// Parse an `If-Range` header value, as received by a server:
+    /// either an entity tag or an HTTP date.
+    ///
+    /// [None] if it is invalid, or is a weak entity tag which can never match,
+    /// in which case the whole representation should be sent.
+    pub fn parse(value: &str) -> Option<Self> {
+        let validator: Validator = match value.trim().parse::<EntityTag>() {
+            Ok(etag) => etag.into(),
+            Err(_) => parse_http_date(value.trim()).ok()?.into(),
+        };
+        Self::new(validator).ok()
+    }
+
+    pub fn validator(&self) -> &Validator {
+        &self.0
+    }
+
+    /// Whether a response with the given validator is for the same version of the representation.
+    pub fn matches(&self, validator: &Validator) -> bool {
+        self.0.matches(validator)
+    }
+
+    pub fn to_header(&self, newline: bool) -> Vec<u8> {
+        let suffix = if newline { "\r\n" } else { "" };
+        format!("{IF_RANGE}: {self}{suffix}").into_bytes()
+    }
+
+    pub fn to_value(&self) -> Vec<u8> {
+        self.to_string().into_bytes()
+    }
+}
+
+impl Display for IfRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// A `(start, exclusive end)` span, where an end of [None] is unbounded.
+type Span = (u64, Option<u64>);
+
+/// Merge spans, sorted by start, which overlap, are adjacent, or are separated by fewer than `gap` bytes;
+/// then, if given, merge those with the smallest separations until there are at most `max_spans`.
+///
+/// Also returns the index of the merged span containing each original span.
+pub(crate) fn coalesce_spans(
+    spans: &[Span],
+    gap: u64,
+    max_spans: Option<usize>,
+) -> (Vec<Span>, Vec<usize>) {
+    let separation = |a: &Span, b: &Span| a.1.map_or(0, |end| b.0.saturating_sub(end));
+    let union = |a: &mut Span, b: Span| a.1 = a.1.zip(b.1).map(|(x, y)| x.max(y));
+
+    let mut merged: Vec<Span> = Vec::with_capacity(spans.len());
+    let mut mapping = Vec::with_capacity(spans.len());
+    for span in spans.iter() {
+        match merged.last_mut() {
+            Some(last) if separation(last, span) < gap.max(1) => union(last, *span),
+            _ => merged.push(*span),
+        }
+        mapping.push(merged.len() - 1);
+    }
+    let max_spans = max_spans.map_or(usize::MAX, |m| m.max(1));
+    while merged.len() > max_spans {
+        let idx = (1..merged.len())
+            .min_by_key(|&i| separation(&merged[i - 1], &merged[i]))
+            .expect("more than one span");
+        let removed = merged.remove(idx);
+        union(&mut merged[idx - 1], removed);
+        for m in mapping.iter_mut().filter(|m| **m >= idx) {
+            *m -= 1;
+        }
+    }
+    (merged, mapping)
+}
+
+/// A [RangeHeader] whose ranges have been sorted and merged by [RangeHeader::normalize],
+/// with a record of where each of the original ranges ended up.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NormalizedRanges<'a> {
+    header: RangeHeader<'a>,
+    /// Merged index and location within the merged range, for each original range.
+    locations: Vec<(usize, Location)>,
+}
+
+impl<'a> NormalizedRanges<'a> {
+    /// The normalized header, to be sent.
+    pub fn header(&self) -> &RangeHeader<'a> {
+        &self.header
+    }
+
+    pub fn into_header(self) -> RangeHeader<'a> {
+        self.header
+    }
+
+    /// For the original range at the given index,
+    /// the index of the normalized range which contains it,
+    /// and where its first byte is within that range.
+    ///
+    /// [None] if there was no original range at that index.
+    pub fn locate(&self, original: usize) -> Option<(usize, Location)> {
+        self.locations.get(original).copied()
+    }
+}
+
+/// Where an original range starts within the normalized range containing it: see [NormalizedRanges::locate].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Location {
+    /// This many bytes after the start of the normalized range.
+    Start(u64),
+    /// For a suffix range folded into a longer suffix while the complete length was unknown:
+    /// this many bytes before the end of the normalized range, or its start if that is shorter.
+    End(u64),
+}
+
+impl Location {
+    /// The offset from the start of a normalized range which turned out to be `len` bytes long,
+    /// e.g. the length of the part of the response which answered it.
+    ///
+    /// ```rust
+    /// # use byteranges::request::{Location, RangeHeader};
+    /// let header = RangeHeader::parse("bytes=-10,-100").unwrap();
+    /// let (_, location) = header.normalize(0, None).locate(0).unwrap();
+    /// assert_eq!(location.offset(100), 90);
+    /// // a 50-byte representation is answered with 50 bytes, the last 10 of which were asked for
+    /// assert_eq!(location.offset(50), 40);
+    /// ```
+    pub fn offset(&self, len: u64) -> u64 {
+        match self {
+            Location::Start(offset) => *offset,
+            Location::End(from_end) => len.saturating_sub(*from_end),
+        }
+    }
+}
+
+/// Works out which ranges still need to be fetched, given the spans of data already held.
+///
+/// ```rust
+/// # use byteranges::request::MissingRanges;
+/// let held = [(0, 100), (150, 10), (500, 100)];
+/// let header = MissingRanges::default().header(held, 50..1000, None);
+/// assert_eq!(header.to_string(), "bytes=100-149,160-499,600-999");
+///
+/// let header = MissingRanges::default()
+///     .coalesce_gap(20)
+///     .header(held, 50.., Some(2000));
+/// assert_eq!(header.to_string(), "bytes=100-499,600-1999");
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MissingRanges {
+    coalesce_gap: u64,
+    max_ranges: Option<usize>,
+}
+
+impl MissingRanges {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Merge missing ranges which are separated by fewer than this many held bytes,
+    /// re-fetching the held bytes to save on ranges.
+    pub fn coalesce_gap(&mut self, gap: u64) -> &mut Self {
+        self.coalesce_gap = gap;
+        self
+    }
+
+    /// Merge the missing ranges with the smallest separations until there are at most this many.
+    ///
+    /// A maximum of 0 is treated as 1.
+    pub fn max_ranges(&mut self, max_ranges: usize) -> &mut Self {
+        self.max_ranges = Some(max_ranges);
+        self
+    }
+
+    /// The `(start, exclusive end)` spans within `wanted` which are not covered by the `held` offsets and lengths.
+    ///
+    /// If the end of `wanted` is unbounded, it is taken to be the `complete_length`;
+    /// if that is also not known, the final span is unbounded.
+    pub fn spans<I: IntoIterator<Item = (usize, usize)>, R: RangeBounds<usize>>(
+        &self,
+        held: I,
+        wanted: R,
+        complete_length: Option<usize>,
+    ) -> Vec<(usize, Option<usize>)> {
+        use std::ops::Bound::*;
+        let start = match wanted.start_bound() {
+            Included(i) => *i,
+            Excluded(i) => i + 1,
+            Unbounded => 0,
+        };
+        let end = match wanted.end_bound() {
+            Included(i) => Some(i + 1),
+            Excluded(i) => Some(*i),
+            Unbounded => complete_length,
+        };
+        let end = match (end, complete_length) {
+            (Some(e), Some(l)) => Some(e.min(l)),
+            (e, _) => e,
+        };
+        let mut held: Vec<_> = held.into_iter().filter(|(_, len)| *len > 0).collect();
+        held.sort_unstable();
+
+        let mut missing = Vec::default();
+        let mut idx = start;
+        for (offset, len) in held {
+            if end.is_some_and(|e| offset >= e) {
+                break;
+            }
+            if offset > idx {
+                missing.push((idx as u64, Some(offset as u64)));
+            }
+            idx = idx.max(offset + len);
+        }
+        if end.is_none_or(|e| idx < e) {
+            missing.push((idx as u64, end.map(|e| e as u64)));
+        }
+        let (merged, _) = coalesce_spans(&missing, self.coalesce_gap, self.max_ranges);
+        merged
+            .into_iter()
+            .map(|(s, e)| (s as usize, e.map(|e| e as usize)))
+            .collect()
+    }
+
+    /// A [RangeHeader] for the ranges within `wanted` which are not covered by the `held` offsets and lengths.
+    ///
+    /// See [MissingRanges::spans]; the header is empty if nothing is missing.
+    pub fn header<I: IntoIterator<Item = (usize, usize)>, R: RangeBounds<usize>>(
+        &self,
+        held: I,
+        wanted: R,
+        complete_length: Option<usize>,
+    ) -> RangeHeader<'static> {
+        self.spans(held, wanted, complete_length)
+            .into_iter()
+            .map(|(start, end)| HttpRange::Range {
+                start: start as u64,
+                end: end.map(|e| e as u64 - 1),
+            })
+            .collect()
+    }
+
+    /// A [RangeHeader] for the ranges within `wanted` which are not covered by the given parts.
+    pub fn header_for_parts<R: RangeBounds<usize>>(
+        &self,
+        parts: &[ResponsePart],
+        wanted: R,
+    ) -> RangeHeader<'static> {
+        let complete_length = parts.iter().find_map(|p| p.total_size());
+        self.header(
+            parts.iter().filter_map(|p| p.offset_len()),
+            wanted,
+            complete_length,
+        )
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
 
     fn test_canonical(header: &RangeHeader, expected: &str) {
         assert_eq!(header.to_string(), expected)
@@ -248,4 +955,318 @@ mod tests {
         let r: RangeHeader = vec![0..50, 40..100, 150..200].into_iter().collect();
         assert_eq!(r.to_string(), "bytes=0-49,40-99,150-199")
     }
+
+    #[test]
+    fn parse_roundtrip() {
+        let header = "bytes=0-49,-100,9500-";
+        assert_eq!(RangeHeader::parse(header).unwrap().to_string(), header);
+    }
+
+    #[test]
+    fn parse_whitespace_and_empty_elements() {
+        let rh = RangeHeader::parse(" bytes= , 0-49 ,,\t-100, ").unwrap();
+        assert_eq!(rh.ranges(), &[(0..50).into(), HttpRange::Suffix(100)]);
+    }
+
+    #[test]
+    fn parse_unit_case_insensitive() {
+        let rh = RangeHeader::parse("BYTES=0-0").unwrap();
+        assert_eq!(rh.unit(), BYTES);
+        let rh = RangeHeader::parse("Pages=1-2").unwrap();
+        assert_eq!(rh.unit(), "Pages");
+    }
+
+    #[test]
+    fn parse_errors() {
+        use RangeParseError::*;
+        for (s, e) in [
+            ("0-49", MissingEquals),
+            ("=0-49", InvalidUnit("".into())),
+            ("by tes=0-49", InvalidUnit("by tes".into())),
+            ("bytes=", EmptyRangeSet),
+            ("bytes= , ,", EmptyRangeSet),
+            ("bytes=-", InvalidRange("-".into())),
+            ("bytes=+1-2", InvalidRange("+1-2".into())),
+            ("bytes=1 - 2", InvalidRange("1 - 2".into())),
+            ("bytes=1-2-3", InvalidRange("1-2-3".into())),
+            ("bytes=10-5", EndBeforeStart("10-5".into())),
+            (
+                "bytes=0-18446744073709551616",
+                Overflow("0-18446744073709551616".into()),
+            ),
+        ] {
+            assert_eq!(RangeHeader::parse(s), Err(e), "{s:?}");
+        }
+    }
+
+    #[test]
+    fn parse_u64_max() {
+        let r: HttpRange = "0-18446744073709551615".parse().unwrap();
+        assert_eq!(
+            r,
+            HttpRange::Range {
+                start: 0,
+                end: Some(u64::MAX)
+            }
+        );
+    }
+
+    #[test]
+    fn resolve_ranges() {
+        let rh = RangeHeader::parse("bytes=0-49,9500-,-100,200-20000,10000-").unwrap();
+        assert_eq!(
+            rh.resolve(10000).unwrap(),
+            vec![(0, 50), (9500, 500), (9900, 100), (200, 9800)]
+        );
+    }
+
+    #[test]
+    fn resolve_suffix_longer_than_file() {
+        let rh: RangeHeader = HttpRange::Suffix(500).into();
+        assert_eq!(rh.resolve(100).unwrap(), vec![(0, 100)]);
+    }
+
+    #[test]
+    fn resolve_unsatisfiable() {
+        let rh = RangeHeader::parse("bytes=100-,200-299,-0").unwrap();
+        assert_eq!(rh.resolve(100), Err(Unsatisfiable(100)));
+    }
+
+    #[test]
+    fn resolve_empty_representation() {
+        let rh = RangeHeader::parse("bytes=0-10,-5").unwrap();
+        assert_eq!(rh.resolve(0).unwrap(), vec![(0, 0)]);
+    }
+
+    #[test]
+    fn missing_nothing_held() {
+        let m = MissingRanges::default();
+        assert_eq!(m.header([], 10..20, None).to_string(), "bytes=10-19");
+        assert_eq!(m.header([], 10.., None).to_string(), "bytes=10-");
+        assert_eq!(m.header([], 10.., Some(15)).to_string(), "bytes=10-14");
+    }
+
+    #[test]
+    fn missing_everything_held() {
+        let m = MissingRanges::default();
+        assert!(m.header([(0, 100)], 10..20, None).is_empty());
+        assert!(m.header([(0, 100)], .., Some(100)).is_empty());
+        assert!(m.header([(0, 100)], 100.., Some(100)).is_empty());
+    }
+
+    #[test]
+    fn missing_overlapping_held() {
+        let m = MissingRanges::default();
+        let held = [(20, 10), (0, 15), (10, 5), (40, 0)];
+        assert_eq!(
+            m.header(held, .., Some(50)).to_string(),
+            "bytes=15-19,30-49"
+        );
+    }
+
+    #[test]
+    fn missing_max_ranges() {
+        let held = [(10, 1), (20, 5), (30, 20), (60, 1)];
+        let header = MissingRanges::default()
+            .max_ranges(3)
+            .header(held, ..70, None);
+        assert_eq!(header.to_string(), "bytes=0-19,25-29,50-69");
+        let header = MissingRanges::default()
+            .max_ranges(0)
+            .header(held, ..70, None);
+        assert_eq!(header.to_string(), "bytes=0-69");
+    }
+
+    #[test]
+    fn normalize_overlap() {
+        let rh: RangeHeader = [500..701, 601..1000].into_iter().collect();
+        let normalized = rh.normalize(0, None);
+        assert_eq!(normalized.header().to_string(), "bytes=500-999");
+        assert_eq!(normalized.locate(0), Some((0, Location::Start(0))));
+        assert_eq!(normalized.locate(1), Some((0, Location::Start(101))));
+        assert_eq!(normalized.locate(2), None);
+    }
+
+    #[test]
+    fn normalize_sorts_and_keeps_mapping() {
+        let rh = RangeHeader::parse("bytes=300-399,0-9,100-199,10-19").unwrap();
+        let normalized = rh.normalize(0, None);
+        assert_eq!(
+            normalized.header().to_string(),
+            "bytes=0-19,100-199,300-399"
+        );
+        let locations: Vec<_> = (0..4)
+            .map(|i| {
+                let (idx, location) = normalized.locate(i).unwrap();
+                (idx, location.offset(100))
+            })
+            .collect();
+        assert_eq!(locations, vec![(2, 0), (0, 0), (1, 0), (0, 10)]);
+    }
+
+    #[test]
+    fn normalize_gap() {
+        let rh = RangeHeader::parse("bytes=0-9,15-19,40-49").unwrap();
+        assert_eq!(
+            rh.normalize(5, None).header().to_string(),
+            "bytes=0-9,15-19,40-49"
+        );
+        assert_eq!(
+            rh.normalize(6, None).header().to_string(),
+            "bytes=0-19,40-49"
+        );
+        assert_eq!(rh.normalize(21, None).header().to_string(), "bytes=0-49");
+    }
+
+    #[test]
+    fn normalize_open_and_suffix() {
+        let rh = RangeHeader::parse("bytes=-50,9500-,-100,9000-9599").unwrap();
+        let normalized = rh.normalize(0, None);
+        assert_eq!(normalized.header().to_string(), "bytes=9000-,-100");
+        assert_eq!(normalized.locate(0), Some((1, Location::End(50))));
+        assert_eq!(normalized.locate(1), Some((0, Location::Start(500))));
+
+        let normalized = rh.normalize(0, Some(10000));
+        assert_eq!(normalized.header().to_string(), "bytes=9000-");
+        assert_eq!(normalized.locate(0), Some((0, Location::Start(950))));
+        assert_eq!(normalized.locate(2), Some((0, Location::Start(900))));
+    }
+
+    #[test]
+    fn normalize_suffixes_longer_than_representation() {
+        let rh = RangeHeader::parse("bytes=-10,-100").unwrap();
+        let normalized = rh.normalize(0, None);
+        assert_eq!(normalized.header().to_string(), "bytes=-100");
+        // a 50-byte representation is answered with all 50 bytes
+        let (offset, len) = normalized.header().ranges()[0].resolve(50).unwrap();
+        assert_eq!((offset, len), (0, 50));
+        let (_, location) = normalized.locate(0).unwrap();
+        assert_eq!(location.offset(len as u64), 40);
+        let (_, location) = normalized.locate(1).unwrap();
+        assert_eq!(location.offset(len as u64), 0);
+        // and a representation shorter than even the shorter suffix with all of it
+        let (_, len) = normalized.header().ranges()[0].resolve(5).unwrap();
+        assert_eq!(normalized.locate(0).unwrap().1.offset(len as u64), 0);
+    }
+
+    #[test]
+    fn normalize_empty_representation() {
+        let rh = RangeHeader::parse("bytes=-1,0-").unwrap();
+        let normalized = rh.normalize(0, Some(0));
+        assert_eq!(normalized.header().to_string(), "bytes=0-,-1");
+    }
+
+    #[test]
+    fn clamp() {
+        let rh = RangeHeader::parse("bytes=10-19,0-,-5").unwrap();
+        assert_eq!(rh.clamp(15).unwrap().to_string(), "bytes=10-14,0-,-5");
+        assert_eq!(rh.clamp(5).unwrap().to_string(), "bytes=0-,-5");
+        // only a suffix can select from an empty representation
+        assert_eq!(rh.clamp(0).unwrap().to_string(), "bytes=-5");
+        let rh = RangeHeader::parse("bytes=10-19").unwrap();
+        assert_eq!(rh.clamp(10), Err(Unsatisfiable(10)));
+    }
+
+    #[test]
+    fn entity_tags() {
+        let strong: EntityTag = "\"abc\"".parse().unwrap();
+        let weak: EntityTag = " W/\"abc\" ".parse().unwrap();
+        assert!(!strong.is_weak());
+        assert!(weak.is_weak());
+        assert_eq!(weak.tag(), "abc");
+        assert!(strong.strong_eq(&EntityTag::strong("abc").unwrap()));
+        assert!(!strong.strong_eq(&weak));
+        assert_eq!(weak.to_string(), "W/\"abc\"");
+        assert_eq!("\"\"".parse::<EntityTag>().unwrap().tag(), "");
+        for s in ["abc", "\"abc", "w/\"abc\"", "\"a\"c\"", "\"a b\""] {
+            assert!(s.parse::<EntityTag>().is_err(), "{s:?}");
+        }
+        assert!(EntityTag::strong("a\"b").is_err());
+    }
+
+    #[test]
+    fn if_range_header() {
+        let if_range = IfRange::new(EntityTag::strong("abc").unwrap()).unwrap();
+        assert_eq!(if_range.to_header(true), b"If-Range: \"abc\"\r\n");
+
+        let date = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1689821552);
+        let if_range = IfRange::new(date).unwrap();
+        assert_eq!(if_range.to_value(), b"Thu, 20 Jul 2023 02:52:32 GMT");
+        assert!(if_range.matches(&Validator::LastModified(date)));
+        assert!(!if_range.matches(&Validator::ETag(EntityTag::strong("abc").unwrap())));
+
+        let weak: EntityTag = "W/\"abc\"".parse().unwrap();
+        assert_eq!(IfRange::new(weak.clone()), Err(WeakETag(weak)));
+    }
+
+    #[test]
+    fn parse_if_range() {
+        let if_range = IfRange::parse(" \"abc\"").unwrap();
+        assert_eq!(
+            if_range,
+            IfRange::new(EntityTag::strong("abc").unwrap()).unwrap()
+        );
+        let date = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1689821552);
+        let if_range = IfRange::parse("Thu, 20 Jul 2023 02:52:32 GMT").unwrap();
+        assert_eq!(if_range, IfRange::new(date).unwrap());
+        assert_eq!(IfRange::parse("W/\"abc\""), None);
+        assert_eq!(IfRange::parse("yesterday"), None);
+    }
+
+    fn http_range() -> impl Strategy<Value = HttpRange> {
+        prop_oneof![
+            (0u64..2000, proptest::option::of(0u64..2000)).prop_map(|(start, end)| {
+                HttpRange::Range {
+                    start,
+                    end: end.map(|e| start + e),
+                }
+            }),
+            (0u64..2000).prop_map(HttpRange::Suffix),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn parse_does_not_panic(s in "(bytes=|BYTES =)?[0-9, -]{0,20}", len in 0usize..100) {
+            if let Ok(rh) = RangeHeader::parse(&s) {
+                if let Ok(resolved) = rh.resolve(len) {
+                    for (offset, l) in resolved {
+                        prop_assert!(offset + l <= len);
+                        prop_assert!(l > 0 || len == 0);
+                    }
+                }
+                let _ = rh.normalize(3, Some(len));
+                let _ = rh.normalize(0, None);
+            }
+        }
+
+        #[test]
+        fn display_roundtrip(ranges in prop::collection::vec(http_range(), 1..10)) {
+            let rh: RangeHeader = ranges.iter().copied().collect();
+            let s = rh.to_string();
+            prop_assert_eq!(RangeHeader::parse(&s).unwrap(), rh);
+        }
+
+        #[test]
+        fn normalized_covers_original(
+            ranges in prop::collection::vec(http_range(), 1..10),
+            gap in 0u64..100,
+            len in 1usize..3000,
+        ) {
+            let rh: RangeHeader = ranges.iter().copied().collect();
+            for complete_length in [Some(len), None] {
+                let normalized = rh.normalize(gap, complete_length);
+                let merged = normalized.header().ranges();
+                for (idx, range) in ranges.iter().enumerate() {
+                    let Some((offset, l)) = range.resolve(len) else {
+                        continue;
+                    };
+                    let (merged_idx, location) = normalized.locate(idx).unwrap();
+                    let (m_offset, m_len) = merged[merged_idx].resolve(len).unwrap();
+                    prop_assert_eq!(m_offset + location.offset(m_len as u64) as usize, offset);
+                    prop_assert!(offset + l <= m_offset + m_len);
+                }
+            }
+        }
+    }
 }
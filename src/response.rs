@@ -1,32 +1,78 @@
 use std::{
-    collections::{btree_map::Entry, BTreeMap},
+    collections::{BTreeMap, VecDeque},
+    convert::Infallible,
     io::{self, Cursor, Read, Seek, SeekFrom},
+    ops::RangeBounds,
+    time::{Duration, SystemTime},
 };
 
 use http_content_range::ContentRange;
 use httparse::{parse_headers, EMPTY_HEADER};
+use httpdate::{fmt_http_date, parse_http_date};
 use rope_rd::sparse::Part;
 use rope_rd::util::abs_position;
 use rope_rd::Node;
 use thiserror::Error;
 
+use crate::media_type::MediaType;
+use crate::multipart::{validate_boundary, MultipartBuilder, MultipartError};
+use crate::request::{EntityTag, IfRange, MissingRanges, RangeHeader, Validator, BYTES};
+use crate::streaming::StreamingParts;
+
+use bytes::{BufMut, BytesMut};
+
 pub use bytes::{Buf, Bytes};
 
-const BYTERANGES: &str = "multipart/byteranges";
+#[cfg(feature = "http-body")]
+pub use crate::impls::http_body_impl::RangeResponder;
+
+pub(crate) const BYTERANGES: &str = "multipart/byteranges";
+pub const CONTENT_TYPE: &str = "Content-Type";
+pub const CONTENT_RANGE: &str = "Content-Range";
+pub const ETAG: &str = "ETag";
+pub const LAST_MODIFIED: &str = "Last-Modified";
 
 /// A component part of a 206 response.
 #[derive(Debug, Clone)]
 pub struct ResponsePart {
     content_type: String,
     content_range: ContentRange,
+    headers: Vec<(String, Bytes)>,
     data: Bytes,
 }
 
 impl ResponsePart {
+    pub(crate) fn new(content_type: String, content_range: ContentRange, data: Bytes) -> Self {
+        Self {
+            content_type,
+            content_range,
+            headers: Vec::default(),
+            data,
+        }
+    }
+
+    pub(crate) fn with_headers(mut self, headers: Vec<(String, Bytes)>) -> Self {
+        self.headers = headers;
+        self
+    }
+
     pub fn content_type(&self) -> &str {
         &self.content_type
     }
 
+    /// All of the part's headers, including `Content-Type` and `Content-Range`, in order.
+    ///
+    /// Only parts of a `multipart/byteranges` response have their own headers:
+    /// for a single-part response, this is empty.
+    pub fn headers(&self) -> &[(String, Bytes)] {
+        &self.headers
+    }
+
+    /// The value of the first of the part's headers with the given name, case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&[u8]> {
+        find_header(&self.headers, name)
+    }
+
     /// The offset and length of the part according to the `Content-Range` header.
     ///
     /// If the range was unsatisfied or the content range was not parseable,
@@ -51,49 +97,182 @@ impl ResponsePart {
     }
 }
 
-fn offset_len(content_range: &ContentRange) -> Option<(usize, usize)> {
+pub(crate) fn find_header<'a>(headers: &'a [(String, Bytes)], name: &str) -> Option<&'a [u8]> {
+    headers
+        .iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|(_, v)| &v[..])
+}
+
+pub(crate) fn offset_len(content_range: &ContentRange) -> Option<(usize, usize)> {
     match content_range {
-        ContentRange::Bytes(r) => Some((
-            r.first_byte as usize,
-            (r.last_byte - r.first_byte + 1) as usize,
-        )),
-        ContentRange::UnboundBytes(r) => Some((
-            r.first_byte as usize,
-            (r.last_byte - r.first_byte + 1) as usize,
-        )),
+        ContentRange::Bytes(r) => span_len(r.first_byte, r.last_byte),
+        ContentRange::UnboundBytes(r) => span_len(r.first_byte, r.last_byte),
         ContentRange::Unsatisfied(_r) => None,
         ContentRange::Unknown => None,
     }
 }
 
+/// Offset and length of an inclusive byte span, if they fit in a [usize].
+fn span_len(first_byte: u64, last_byte: u64) -> Option<(usize, usize)> {
+    let offset = usize::try_from(first_byte).ok()?;
+    let len = usize::try_from(last_byte - first_byte)
+        .ok()?
+        .checked_add(1)?;
+    Some((offset, len))
+}
+
 /// A description of the partial response headers.
 ///
 /// This may be a single part, in which case the `Content-Range` and `Content-Type` values are known,
 /// or a `mutipart/byteranges` response, in which case the `boundary` is known.
+#[derive(Debug)]
 pub enum PartDesc {
     Single {
         content_range: ContentRange,
         content_type: String,
     },
     Multi {
+        /// The boundary preceded by `--`, as it appears in the body.
         boundary: Vec<u8>,
     },
 }
 
+/// Error from interpreting a response as a 206 Partial.
+///
+/// `E` is the error from reading the response body, if it was read
+/// (see [MaybePartialResponse::Error]).
 #[derive(Debug, Error)]
-pub enum PartialHeaderParseError {
-    #[error("Range response could not be satisfied (status 416)")]
-    Unsatisfied,
+pub enum PartialHeaderParseError<E = Infallible> {
+    #[error(
+        "Range response could not be satisfied (status 416); complete length {}",
+        .complete_length.map_or_else(|| "unknown".to_owned(), |l| l.to_string())
+    )]
+    Unsatisfied { complete_length: Option<usize> },
     #[error("Expected response 206, got {0}")]
     NotPartialResponse(u16),
     #[error("No Content-Type header found")]
     NoContentType,
+    #[error("Could not parse Content-Type header: {0}")]
+    ContentTypeParse(String),
+    #[error("No boundary parameter in multipart/byteranges Content-Type")]
+    NoBoundary,
+    #[error("Invalid multipart boundary {0:?}")]
+    InvalidBoundary(String),
     #[error("No Content-Range header found")]
     NoContentRange,
     #[error("Could not parse Content-Range header: {0}")]
     ContentRangeParse(String),
+    #[error("Partial response has an unsatisfied Content-Range {0:?}")]
+    UnsatisfiedContentRange(String),
+    #[error("Conflicting {name} headers: {values:?}")]
+    ConflictingHeaders { name: String, values: Vec<String> },
     #[error(transparent)]
-    BodyRead(#[from] Box<dyn std::error::Error>),
+    BodyRead(E),
+}
+
+impl<E> PartialHeaderParseError<E> {
+    /// The complete length reported by a 416 response, if any.
+    pub fn unsatisfied_length(&self) -> Option<usize> {
+        match self {
+            Self::Unsatisfied { complete_length } => *complete_length,
+            _ => None,
+        }
+    }
+}
+
+impl PartialHeaderParseError {
+    /// The same error, for a response whose body has the error type `E`.
+    pub fn widen<E>(self) -> PartialHeaderParseError<E> {
+        use PartialHeaderParseError::*;
+        match self {
+            Unsatisfied { complete_length } => Unsatisfied { complete_length },
+            NotPartialResponse(n) => NotPartialResponse(n),
+            NoContentType => NoContentType,
+            ContentTypeParse(s) => ContentTypeParse(s),
+            NoBoundary => NoBoundary,
+            InvalidBoundary(s) => InvalidBoundary(s),
+            NoContentRange => NoContentRange,
+            ContentRangeParse(s) => ContentRangeParse(s),
+            UnsatisfiedContentRange(s) => UnsatisfiedContentRange(s),
+            ConflictingHeaders { name, values } => ConflictingHeaders { name, values },
+            BodyRead(e) => match e {},
+        }
+    }
+}
+
+/// The complete length from an unsatisfied `Content-Range` value like `bytes */1234`.
+pub(crate) fn unsatisfied_length(content_range: Option<&str>) -> Option<usize> {
+    match ContentRange::parse(content_range?) {
+        ContentRange::Unsatisfied(r) => usize::try_from(r.complete_length).ok(),
+        _ => None,
+    }
+}
+
+/// The value of a header which may only appear once, from all of its values.
+///
+/// Repeats of the same value are tolerated, but differing values are an error.
+pub(crate) fn single_value<'a>(
+    name: &str,
+    values: Vec<&'a str>,
+) -> Result<Option<&'a str>, PartialHeaderParseError> {
+    let Some(first) = values.first().copied() else {
+        return Ok(None);
+    };
+    if values.iter().any(|v| v.trim() != first.trim()) {
+        return Err(PartialHeaderParseError::ConflictingHeaders {
+            name: name.to_owned(),
+            values: values.into_iter().map(str::to_owned).collect(),
+        });
+    }
+    Ok(Some(first))
+}
+
+/// Describe the parts of a response from its status code and `Content-Type` and `Content-Range` header values.
+pub(crate) fn part_description(
+    status: u16,
+    content_type: Option<&str>,
+    content_range: Option<&str>,
+) -> Result<PartDesc, PartialHeaderParseError> {
+    use PartialHeaderParseError::*;
+    match status {
+        416 => Err(Unsatisfied {
+            complete_length: unsatisfied_length(content_range),
+        }),
+        206 => Ok(()),
+        n => Err(NotPartialResponse(n)),
+    }?;
+    let s = content_type.ok_or(NoContentType)?.trim();
+    let media_type = MediaType::parse(s);
+    // only multipart responses need to be understood,
+    // so be lenient with other content types
+    let is_multi = match &media_type {
+        Ok(mt) => mt.is(BYTERANGES),
+        Err(_) => s
+            .get(..BYTERANGES.len())
+            .is_some_and(|prefix| prefix.eq_ignore_ascii_case(BYTERANGES)),
+    };
+
+    if is_multi {
+        let media_type = media_type.map_err(|e| ContentTypeParse(e.0))?;
+        let boundary_str = media_type.param("boundary").ok_or(NoBoundary)?;
+        validate_boundary(boundary_str).map_err(|_| InvalidBoundary(boundary_str.to_owned()))?;
+        let boundary = format!("--{boundary_str}").as_bytes().to_vec();
+        Ok(PartDesc::Multi { boundary })
+    } else {
+        let cr_s = content_range.ok_or(NoContentRange)?;
+        let mut cr = ContentRange::parse(cr_s);
+        cr = match cr {
+            // a 206 response must not have an unsatisfied range
+            ContentRange::Unsatisfied(_) => Err(UnsatisfiedContentRange(cr_s.to_owned())),
+            ContentRange::Unknown => Err(ContentRangeParse(cr_s.to_owned())),
+            _ => Ok(cr),
+        }?;
+        Ok(PartDesc::Single {
+            content_range: cr,
+            content_type: s.to_owned(),
+        })
+    }
 }
 
 /// Trait for a response which may be a 206 Partial.
@@ -102,56 +281,110 @@ pub enum PartialHeaderParseError {
 /// and [reqwest::blocking::Response](https://docs.rs/reqwest/latest/reqwest/struct.Response.html)
 /// behind the relevant feature flags.
 pub trait MaybePartialResponse: Sized {
+    /// Error from reading the response body.
+    type Error: std::error::Error + Send + Sync + 'static;
+
     fn status_code(&self) -> u16;
 
-    /// Value of the response's `Content-Type` header if present.
-    fn content_type_str(&self) -> Option<&str>;
+    /// Values of all of the response's headers with the given name, compared case-insensitively, in order.
+    ///
+    /// Values which are not valid strings are skipped.
+    fn header_values(&self, name: &str) -> Vec<&str>;
+
+    /// Value of the response's `Content-Type` header if present, and not given conflicting values.
+    fn content_type_str(&self) -> Option<&str> {
+        single_value(CONTENT_TYPE, self.header_values(CONTENT_TYPE)).ok()?
+    }
+
+    /// Value of the response's `Content-Range` header if present, and not given conflicting values.
+    fn content_range_str(&self) -> Option<&str> {
+        single_value(CONTENT_RANGE, self.header_values(CONTENT_RANGE)).ok()?
+    }
+
+    /// Value of the response's `ETag` header if present, and not given conflicting values.
+    fn etag_str(&self) -> Option<&str> {
+        single_value(ETAG, self.header_values(ETAG)).ok()?
+    }
 
-    /// Value of the response's `Content-Range` header if present.
-    fn content_range_str(&self) -> Option<&str>;
+    /// Value of the response's `Last-Modified` header if present, and not given conflicting values.
+    fn last_modified_str(&self) -> Option<&str> {
+        single_value(LAST_MODIFIED, self.header_values(LAST_MODIFIED)).ok()?
+    }
 
     /// The bytes of the response body.
-    // todo: could this error be generic instead?
-    fn body(self) -> Result<Bytes, Box<dyn std::error::Error>>;
+    fn body(self) -> Result<Bytes, Self::Error>;
+
+    /// A reader over the response body.
+    ///
+    /// By default, this reads the whole [MaybePartialResponse::body] into memory;
+    /// implementors whose body can be streamed should override it.
+    fn body_reader<'a>(self) -> Result<Box<dyn Read + 'a>, Self::Error>
+    where
+        Self: 'a,
+    {
+        Ok(Box::new(Cursor::new(self.body()?)))
+    }
+
+    /// The response's entity tag, if it has a valid one.
+    fn etag(&self) -> Option<EntityTag> {
+        self.etag_str()?.parse().ok()
+    }
+
+    /// The response's `Last-Modified` date, if it has a valid one.
+    fn last_modified(&self) -> Option<SystemTime> {
+        parse_http_date(self.last_modified_str()?).ok()
+    }
+
+    /// The response's [Validator]: its entity tag if it has one, otherwise its `Last-Modified` date.
+    fn validator(&self) -> Option<Validator> {
+        validator(self.etag(), self.last_modified())
+    }
+
+    /// Whether the response is for the version of the representation identified by the [IfRange] sent with the request.
+    ///
+    /// If not, the server has ignored the `Range` because the representation changed,
+    /// and any data fetched before is out of date.
+    fn matches_if_range(&self, if_range: &IfRange) -> bool {
+        matches_if_range(
+            self.status_code(),
+            self.etag(),
+            self.last_modified(),
+            if_range,
+        )
+    }
 
     /// If the response is a 206 Partial, a description of what type based on the headers.
+    ///
+    /// Fails with [PartialHeaderParseError::ConflictingHeaders] if the `Content-Type` or `Content-Range`
+    /// is given more than once with different values.
     fn part_description(&self) -> Result<PartDesc, PartialHeaderParseError> {
-        use PartialHeaderParseError::*;
-        let status = self.status_code();
-        match status {
-            416 => Err(Unsatisfied),
-            206 => Ok(()),
-            n => Err(NotPartialResponse(n)),
-        }?;
-        let mut s = self.content_type_str().ok_or(NoContentType)?;
-
-        s = s.trim();
-        if s.starts_with(BYTERANGES) {
-            let boundary_str = s[..BYTERANGES.len() + 1].trim_start()[9..]
-                .trim_matches('"')
-                .trim_matches('\'');
-            let boundary = format!("--{boundary_str}").as_bytes().to_vec();
-            Ok(PartDesc::Multi { boundary })
-        } else {
-            let cr_s = self.content_range_str().ok_or(NoContentRange)?;
-            let mut cr = ContentRange::parse(cr_s);
-            cr = match cr {
-                // ContentRange::Bytes(_) => todo!(),
-                // ContentRange::UnboundBytes(_) => todo!(),
-                ContentRange::Unsatisfied(_) => unreachable!(),
-                ContentRange::Unknown => Err(ContentRangeParse(cr_s.to_owned())),
-                _ => Ok(cr),
-            }?;
-            Ok(PartDesc::Single {
-                content_range: cr,
-                content_type: s.to_owned(),
-            })
-        }
+        part_description(
+            self.status_code(),
+            single_value(CONTENT_TYPE, self.header_values(CONTENT_TYPE))?,
+            single_value(CONTENT_RANGE, self.header_values(CONTENT_RANGE))?,
+        )
     }
 
     /// If the response is a 206 Partial, an iterator over its [ResponsePart]s.
-    fn parts(self) -> Result<Parts, PartialHeaderParseError> {
-        Ok(Parts::new(self.part_description()?, self.body()?))
+    fn parts(self) -> Result<Parts, PartialHeaderParseError<Self::Error>> {
+        let part_desc = self.part_description().map_err(|e| e.widen())?;
+        let body = self.body().map_err(PartialHeaderParseError::BodyRead)?;
+        Ok(Parts::new(part_desc, body))
+    }
+
+    /// If the response is a 206 Partial, a [StreamingParts] which reads the parts from the [MaybePartialResponse::body_reader]
+    /// without buffering the whole body.
+    fn streaming_parts<'a>(
+        self,
+    ) -> Result<StreamingParts<Box<dyn Read + 'a>>, PartialHeaderParseError<Self::Error>>
+    where
+        Self: 'a,
+    {
+        let part_desc = self.part_description().map_err(|e| e.widen())?;
+        let body = self
+            .body_reader()
+            .map_err(PartialHeaderParseError::BodyRead)?;
+        Ok(StreamingParts::new(part_desc, body))
     }
 
     /// Representation of the whole requested file, with [Read]/[Seek].
@@ -161,83 +394,497 @@ pub trait MaybePartialResponse: Sized {
     /// If the response was a 206 Partial, only the parts in the response will be the "real" file: the remainder will be null bytes.
     /// This does not take up the memory that the whole file would, as the [SparseBody] generates the filler material on the fly.
     ///
-    /// Responses which contain overlapping ranges will cause unexpected behaviour; blame the server.
-    fn sparse_body(self) -> Result<SparseBody, SparseBodyError> {
-        if self.status_code() == 200 {
-            return Ok(SparseBody::full(self.body()?));
+    /// Where the response contains overlapping ranges, the one which starts earlier, or is longer, takes precedence
+    /// (see [SparseBody::extend_parts]).
+    ///
+    /// More responses can be added later with [SparseBody::insert_response].
+    fn sparse_body(self) -> Result<SparseBody, SparseBodyError<Self::Error>> {
+        let mut body = SparseBody::new();
+        body.insert_response(self)?;
+        Ok(body)
+    }
+
+    /// As [MaybePartialResponse::sparse_body], for a request sent with the given `If-Range`.
+    ///
+    /// Fails with [SparseBodyError::ValidatorMismatch] if the representation has changed
+    /// (see [MaybePartialResponse::matches_if_range]),
+    /// so that it is not mixed with data fetched before.
+    fn sparse_body_if_range(
+        self,
+        if_range: &IfRange,
+    ) -> Result<SparseBody, SparseBodyError<Self::Error>> {
+        if !self.matches_if_range(if_range) {
+            return Err(SparseBodyError::ValidatorMismatch {
+                expected: if_range.validator().clone(),
+                found: self.validator(),
+            });
+        }
+        self.sparse_body()
+    }
+}
+
+/// Send a range request with `send`; if the server responds 416 Range Not Satisfiable
+/// and reports the complete length, send it again with the ranges clamped to that length
+/// (see [RangeHeader::clamp]).
+///
+/// Some servers refuse ranges which extend past the end of the representation,
+/// rather than returning the bytes which are there.
+/// Otherwise, the first response is returned.
+pub fn retry_clamped<'a, R, E, F>(range: RangeHeader<'a>, mut send: F) -> Result<R, E>
+where
+    R: MaybePartialResponse,
+    F: FnMut(RangeHeader<'a>) -> Result<R, E>,
+{
+    let response = send(range.clone())?;
+    if response.status_code() != 416 {
+        return Ok(response);
+    }
+    match unsatisfied_length(response.content_range_str()).map(|l| range.clamp(l)) {
+        Some(Ok(clamped)) if clamped != range => send(clamped),
+        _ => Ok(response),
+    }
+}
+
+/// Prefer the entity tag.
+pub(crate) fn validator(
+    etag: Option<EntityTag>,
+    last_modified: Option<SystemTime>,
+) -> Option<Validator> {
+    etag.map(Validator::ETag)
+        .or(last_modified.map(Validator::LastModified))
+}
+
+/// Compare a response's validator of the same kind as the `If-Range`'s.
+///
+/// A 206 response without one is trusted, as the server only sends it if the `If-Range` matched.
+pub(crate) fn matches_if_range(
+    status: u16,
+    etag: Option<EntityTag>,
+    last_modified: Option<SystemTime>,
+    if_range: &IfRange,
+) -> bool {
+    let found = match if_range.validator() {
+        Validator::ETag(_) => etag.map(Validator::ETag),
+        Validator::LastModified(_) => last_modified.map(Validator::LastModified),
+    };
+    match found {
+        Some(v) => if_range.matches(&v),
+        None => status == 206,
+    }
+}
+
+/// What a response reported about the representation it was taken from.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Source {
+    pub etag: Option<EntityTag>,
+    pub last_modified: Option<SystemTime>,
+    /// From the length of a 200 response's body or a 206 response's `Content-Range`.
+    pub complete_length: Option<usize>,
+}
+
+impl Source {
+    pub(crate) fn new(etag: Option<EntityTag>, last_modified: Option<SystemTime>) -> Self {
+        Self {
+            etag,
+            last_modified,
+            complete_length: None,
+        }
+    }
+
+    pub(crate) fn from_response<R: MaybePartialResponse>(response: &R) -> Self {
+        Self::new(response.etag(), response.last_modified())
+    }
+
+    /// The entity tag if there is one, otherwise the modification date.
+    pub fn validator(&self) -> Option<Validator> {
+        validator(self.etag.clone(), self.last_modified)
+    }
+
+    /// Validators of the same kind which show that the two sources are different representations,
+    /// as (this source's, the other's).
+    ///
+    /// Entity tags are compared weakly, so that a change of weakness alone is not a conflict.
+    pub fn conflict(&self, other: &Self) -> Option<(Validator, Validator)> {
+        if let (Some(a), Some(b)) = (&self.etag, &other.etag) {
+            if a.tag() != b.tag() {
+                return Some((a.clone().into(), b.clone().into()));
+            }
+        }
+        match (self.last_modified, other.last_modified) {
+            (Some(a), Some(b)) if a != b => Some((a.into(), b.into())),
+            _ => None,
+        }
+    }
+}
+
+fn merge_complete_length(
+    current: Option<usize>,
+    new: Option<usize>,
+) -> Result<Option<usize>, SparseBodyError> {
+    match (current, new) {
+        (Some(expected), Some(found)) if expected != found => {
+            Err(SparseBodyError::LengthMismatch { expected, found })
         }
-        let pv: Result<Vec<ResponsePart>, PartParseError> = self.parts()?.collect();
-        Ok(SparseBody::partial(pv?))
+        _ => Ok(current.or(new)),
     }
 }
 
+/// Error from building a [SparseBody].
+///
+/// `E` is the error from reading a response body (see [MaybePartialResponse::Error]).
 #[derive(Debug, Error)]
-pub enum SparseBodyError {
+pub enum SparseBodyError<E = Infallible> {
     #[error(transparent)]
-    Header(#[from] PartialHeaderParseError),
+    Header(#[from] PartialHeaderParseError<E>),
     #[error(transparent)]
     Part(#[from] PartParseError),
     #[error(transparent)]
-    Body(#[from] Box<dyn std::error::Error>),
+    Body(E),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("Complete length {found} conflicts with previously reported {expected}")]
+    LengthMismatch { expected: usize, found: usize },
+    #[error("Data ends at {end}, beyond the complete length {complete_length}")]
+    OutOfBounds { end: usize, complete_length: usize },
+    #[error(
+        "Validator {} does not match {expected}: the representation has changed",
+        .found.as_ref().map_or_else(|| "(none)".to_owned(), |v| v.to_string())
+    )]
+    ValidatorMismatch {
+        expected: Validator,
+        found: Option<Validator>,
+    },
+}
+
+impl SparseBodyError {
+    /// The same error, for a response whose body has the error type `E`.
+    pub fn widen<E>(self) -> SparseBodyError<E> {
+        use SparseBodyError::*;
+        match self {
+            Header(e) => Header(e.widen()),
+            Part(e) => Part(e),
+            Body(e) => match e {},
+            Io(e) => Io(e),
+            LengthMismatch { expected, found } => LengthMismatch { expected, found },
+            OutOfBounds {
+                end,
+                complete_length,
+            } => OutOfBounds {
+                end,
+                complete_length,
+            },
+            ValidatorMismatch { expected, found } => ValidatorMismatch { expected, found },
+        }
+    }
 }
 
-// Iterator over parts of a 206 Partial response.
+/// Iterator over parts of a 206 Partial response.
+///
+/// Once an error has been returned, the iterator is exhausted.
+///
+/// Any preamble before the first boundary and epilogue after the last is ignored,
+/// as allowed by [RFC 2046 §5.1.1](https://www.rfc-editor.org/rfc/rfc2046#section-5.1.1).
+/// See [Parts::set_lenient] for accepting other deviations from the spec.
 pub struct Parts {
     part_desc: PartDesc,
     body: Bytes,
+    /// `CRLF--boundary`, which ends each part's data.
+    delimiter: Vec<u8>,
     is_done: bool,
-    next_start: usize,
+    /// Index just past the most recent boundary, if one has been found.
+    next_start: Option<usize>,
+    lenient: bool,
 }
 
 impl Parts {
     pub fn new(part_desc: PartDesc, body: Bytes) -> Self {
-        let mut next_start = 0;
-        let mut is_done = false;
-        let boundary = match &part_desc {
-            PartDesc::Single { .. } => {
-                return Self {
-                    part_desc,
-                    body,
-                    is_done,
-                    next_start,
-                }
+        let delimiter = match &part_desc {
+            PartDesc::Single { .. } => Vec::default(),
+            PartDesc::Multi { boundary } => [b"\r\n", boundary.as_slice()].concat(),
+        };
+        Self {
+            part_desc,
+            body,
+            delimiter,
+            is_done: false,
+            next_start: None,
+            lenient: false,
+        }
+    }
+
+    /// Accept multipart bodies which use bare `LF` line endings,
+    /// or which have whitespace ("transport padding") after a boundary.
+    ///
+    /// Where a part's `Content-Range` gives its length, that is used to find the end of its data,
+    /// so that data ending in `CR` is not mistaken for a `CRLF` line ending.
+    ///
+    /// Off by default.
+    pub fn set_lenient(&mut self, lenient: bool) -> &mut Self {
+        self.lenient = lenient;
+        self
+    }
+
+    pub fn is_lenient(&self) -> bool {
+        self.lenient
+    }
+
+    fn next_multi(&mut self) -> Result<Option<ResponsePart>, PartParseError> {
+        let boundary = &self.delimiter[2..];
+        let mut after_boundary = match self.next_start {
+            Some(idx) => idx,
+            None => {
+                check_boundary(boundary)?;
+                // the first boundary need not be preceded by CRLF
+                let idx = find(&self.body, boundary)
+                    .ok_or(PartParseError::new(0, PartParseErrorKind::NoBoundary))?;
+                idx + boundary.len()
             }
-            PartDesc::Multi { boundary } => boundary,
+        };
+        if self.lenient {
+            after_boundary += self.body[after_boundary..]
+                .iter()
+                .take_while(|b| **b == b' ' || **b == b'\t')
+                .count();
         }
-        .as_slice();
+        let tail = &self.body[after_boundary..];
+        let start = if tail.starts_with(b"--") {
+            return Ok(None);
+        } else if tail.starts_with(b"\r\n") {
+            after_boundary + 2
+        } else if self.lenient && tail.starts_with(b"\n") {
+            after_boundary + 1
+        } else {
+            return Err(PartParseError::new(
+                after_boundary,
+                PartParseErrorKind::MissingCrlf,
+            ));
+        };
 
-        for (start_idx, window) in body.windows(boundary.len()).enumerate() {
-            if window != boundary {
-                continue;
+        // LF--boundary also matches CRLF--boundary
+        let delimiter = if self.lenient {
+            &self.delimiter[1..]
+        } else {
+            &self.delimiter[..]
+        };
+        let found = find(&self.body[start..], delimiter).map(|idx| start + idx);
+        let slice = &self.body[start..found.unwrap_or(self.body.len())];
+        let Some(head) = parse_part_headers(slice, start, |v| self.body.slice_ref(v))? else {
+            return Err(PartParseError::new(
+                start,
+                PartParseErrorKind::TruncatedHeaders,
+            ));
+        };
+        let data_start = start + head.len;
+        let end = if self.lenient {
+            self.lenient_end(data_start, &head.content_range, found)
+        } else {
+            found.map(|idx| (idx, idx + delimiter.len()))
+        };
+        let Some((end, next_start)) = end else {
+            return Err(PartParseError::new(
+                data_start,
+                PartParseErrorKind::MissingDelimiter,
+            ));
+        };
+        self.next_start = Some(next_start);
+
+        let part = ResponsePart::new(
+            head.content_type,
+            head.content_range,
+            self.body.slice(data_start..end),
+        )
+        .with_headers(head.headers);
+        check_part_len(&part.content_range, part.data.len(), data_start)?;
+        Ok(Some(part))
+    }
+
+    /// Find the end of the data of a part, and the index just past the following boundary.
+    ///
+    /// `found` is the index of the first `LF--boundary` after the part's headers.
+    fn lenient_end(
+        &self,
+        data_start: usize,
+        content_range: &ContentRange,
+        found: Option<usize>,
+    ) -> Option<(usize, usize)> {
+        let boundary = &self.delimiter[2..];
+        let from_length = offset_len(content_range).and_then(|(_, len)| {
+            let end = data_start.checked_add(len)?;
+            let rest = self.body.get(end..)?;
+            let rest = rest
+                .strip_prefix(b"\r\n")
+                .or_else(|| rest.strip_prefix(b"\n"))?;
+            rest.starts_with(boundary)
+                .then(|| (end, self.body.len() - rest.len() + boundary.len()))
+        });
+        if from_length.is_some() {
+            return from_length;
+        }
+        let idx = found?;
+        let end = if idx > data_start && self.body[idx - 1] == b'\r' {
+            idx - 1
+        } else {
+            idx
+        };
+        Some((end, idx + 1 + boundary.len()))
+    }
+}
+
+pub(crate) fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// Number of headers to parse a part's headers into at first.
+const INITIAL_HEADERS: usize = 16;
+
+/// The parsed headers at the start of a part.
+pub(crate) struct PartHead {
+    /// Length of the headers, including the blank line which ends them.
+    pub len: usize,
+    pub content_type: String,
+    pub content_range: ContentRange,
+    pub headers: Vec<(String, Bytes)>,
+}
+
+/// Parse the headers at the start of a part, whose first byte is at `offset` in the body.
+///
+/// Header values are converted to [Bytes] with `to_bytes`.
+/// Returns [None] if the headers are incomplete.
+pub(crate) fn parse_part_headers<F: Fn(&[u8]) -> Bytes>(
+    buf: &[u8],
+    offset: usize,
+    to_bytes: F,
+) -> Result<Option<PartHead>, PartParseError> {
+    let mut n_headers = INITIAL_HEADERS;
+    loop {
+        let mut headers = vec![EMPTY_HEADER; n_headers];
+        match parse_headers(buf, &mut headers) {
+            Err(httparse::Error::TooManyHeaders) => n_headers *= 2,
+            Err(_) => {
+                return Err(PartParseError::new(
+                    offset,
+                    PartParseErrorKind::InvalidHeaders,
+                ))
+            }
+            Ok(httparse::Status::Partial) => return Ok(None),
+            Ok(httparse::Status::Complete((len, heads))) => {
+                return part_head(len, heads, offset, to_bytes).map(Some)
             }
-            let end_idx = start_idx + window.len();
-            // +2 accounts for CRLF or --
-            let tail_end_idx = end_idx + 2;
-            let tail = &body[end_idx..tail_end_idx];
-            match tail {
-                b"\r\n" => {
-                    next_start = tail_end_idx;
-                    break;
-                }
-                b"--" => {
-                    is_done = true;
-                    break;
-                }
-                t => panic!("Boundary not followed by CRLF or double hyphen: {t:?}"),
-            };
         }
-        Self {
-            part_desc,
-            body,
-            is_done,
-            next_start,
+    }
+}
+
+fn part_head<F: Fn(&[u8]) -> Bytes>(
+    len: usize,
+    heads: &[httparse::Header],
+    offset: usize,
+    to_bytes: F,
+) -> Result<PartHead, PartParseError> {
+    let mut content_range = None;
+    let mut content_type = None;
+    for head in heads.iter() {
+        if content_range.is_none() && head.name.eq_ignore_ascii_case("content-range") {
+            content_range = Some(ContentRange::parse_bytes(head.value));
+        } else if content_type.is_none() && head.name.eq_ignore_ascii_case("content-type") {
+            content_type = Some(head.value);
         }
     }
+    let content_range = content_range.ok_or(PartParseError::new(
+        offset,
+        PartParseErrorKind::NoContentRange,
+    ))?;
+    let content_type = content_type.ok_or(PartParseError::new(
+        offset,
+        PartParseErrorKind::NoContentType,
+    ))?;
+    let content_type = String::from_utf8(content_type.to_vec())
+        .map_err(|_| PartParseError::new(offset, PartParseErrorKind::NonUtf8ContentType))?;
+    let headers = heads
+        .iter()
+        .map(|h| (h.name.to_owned(), to_bytes(h.value)))
+        .collect();
+    Ok(PartHead {
+        len,
+        content_type,
+        content_range,
+        headers,
+    })
+}
+
+/// Check that a part's data, starting at `offset` in the body, has the length given in its `Content-Range`.
+pub(crate) fn check_part_len(
+    content_range: &ContentRange,
+    len: usize,
+    offset: usize,
+) -> Result<(), PartParseError> {
+    match offset_len(content_range) {
+        Some((_, expected)) if expected != len => Err(PartParseError::new(
+            offset,
+            PartParseErrorKind::LengthMismatch {
+                expected,
+                found: len,
+            },
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Why a part of a response could not be parsed.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum PartParseErrorKind {
+    #[error("no multipart boundary found")]
+    NoBoundary,
+    /// The boundary given in the [PartDesc] is not `--` followed by a valid boundary.
+    #[error("multipart boundary is empty or invalid")]
+    InvalidBoundary,
+    #[error("boundary not followed by CRLF or double hyphen")]
+    MissingCrlf,
+    #[error("part headers are truncated")]
+    TruncatedHeaders,
+    #[error("part headers are too long")]
+    HeadersTooLong,
+    #[error("part headers are malformed")]
+    InvalidHeaders,
+    #[error("part has no Content-Range header")]
+    NoContentRange,
+    #[error("part has no Content-Type header")]
+    NoContentType,
+    #[error("part Content-Type is not valid UTF-8")]
+    NonUtf8ContentType,
+    #[error("part data is not followed by a boundary")]
+    MissingDelimiter,
+    /// When streaming, data longer than expected is reported as soon as it is received,
+    /// so `found` may be less than the whole length of the data.
+    #[error("part has {found} bytes of data but its Content-Range gives {expected}")]
+    LengthMismatch { expected: usize, found: usize },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("Could not parse part at byte {offset} of the body: {kind}")]
+pub struct PartParseError {
+    /// Position in the response body where the problem was found.
+    pub offset: usize,
+    pub kind: PartParseErrorKind,
+}
+
+impl PartParseError {
+    pub(crate) fn new(offset: usize, kind: PartParseErrorKind) -> Self {
+        Self { offset, kind }
+    }
 }
 
-#[derive(Debug, Copy, Clone, Error)]
-#[error("Could not parse part headers")]
-pub struct PartParseError();
+/// Check the boundary of a [PartDesc::Multi] before searching the body for it.
+pub(crate) fn check_boundary(boundary: &[u8]) -> Result<(), PartParseError> {
+    let valid = boundary
+        .strip_prefix(b"--")
+        .and_then(|b| std::str::from_utf8(b).ok())
+        .is_some_and(|b| validate_boundary(b).is_ok());
+    if valid {
+        Ok(())
+    } else {
+        Err(PartParseError::new(0, PartParseErrorKind::InvalidBoundary))
+    }
+}
 
 impl Iterator for Parts {
     type Item = Result<ResponsePart, PartParseError>;
@@ -246,85 +893,22 @@ impl Iterator for Parts {
         if self.is_done {
             return None;
         }
-        let boundary = match &self.part_desc {
-            PartDesc::Single {
-                content_range,
-                content_type,
-            } => {
-                self.is_done = true;
-                return Some(Ok(ResponsePart {
-                    content_type: content_type.to_string(),
-                    content_range: *content_range,
-                    data: self.body.clone(),
-                }));
-            }
-            PartDesc::Multi { boundary } => boundary,
-        }
-        .as_slice();
-
-        for (offset, window) in self.body[self.next_start..]
-            .windows(boundary.len())
-            .enumerate()
+        if let PartDesc::Single {
+            content_range,
+            content_type,
+        } = &self.part_desc
         {
-            if window != boundary {
-                continue;
-            }
-            // -2 accounts for CRLF
-            let prev_end = offset + self.next_start - 2;
-
-            let slice = self.body.slice(self.next_start..prev_end);
-
-            // +2 accounts for CRLF between boundary and header, or -- to mark end
-            self.next_start += offset + boundary.len() + 2;
-
-            self.is_done = match &self.body[self.next_start - 2..self.next_start] {
-                b"\r\n" => false,
-                b"--" => true,
-                t => panic!("Boundary not followed by CRLF or double hyphen: {t:?}"),
-            };
-            let mut headers = [EMPTY_HEADER; 10];
-            let Ok(status) = parse_headers(&slice[..], &mut headers) else {
-                return Some(Err(PartParseError()))
-            };
-            if status.is_partial() {
-                return Some(Err(PartParseError()));
-            }
-            let (idx, heads) = status.unwrap();
-            let data = slice.slice(idx..);
-            let mut content_range = None;
-            let mut content_type = None;
-            for head in heads.iter() {
-                match head.name.to_lowercase().as_str() {
-                    "content-range" => {
-                        content_range = Some(ContentRange::parse_bytes(head.value));
-                        if content_type.is_some() {
-                            break;
-                        }
-                    }
-                    "content-type" => {
-                        content_type = Some(head.value.to_owned());
-                        if content_range.is_some() {
-                            break;
-                        }
-                    }
-                    _ => continue,
-                }
-                let Some(cr) = content_range else {return Some(Err(PartParseError()))};
-                let Some(ct) = content_type else {
-                    return Some(Err(PartParseError()))
-                };
-                let Ok(ct_s) = String::from_utf8(ct) else {
-                    return Some(Err(PartParseError()))
-                };
-                return Some(Ok(ResponsePart {
-                    content_type: ct_s,
-                    content_range: cr,
-                    data,
-                }));
-            }
+            self.is_done = true;
+            let part =
+                ResponsePart::new(content_type.to_string(), *content_range, self.body.clone());
+            return Some(check_part_len(content_range, self.body.len(), 0).map(|_| part));
         }
 
-        None
+        let result = self.next_multi();
+        if !matches!(result, Ok(Some(_))) {
+            self.is_done = true;
+        }
+        result.transpose()
     }
 }
 
@@ -342,9 +926,11 @@ impl BytesRS {
 
 impl Read for BytesRS {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        let mut cur = Cursor::new(&self.bytes[..]);
-        cur.seek(SeekFrom::Start(self.position))?;
-        let n_read = cur.read(buf)?;
+        let start = usize::try_from(self.position)
+            .unwrap_or(usize::MAX)
+            .min(self.bytes.len());
+        let n_read = buf.len().min(self.bytes.len() - start);
+        buf[..n_read].copy_from_slice(&self.bytes[start..start + n_read]);
         self.position += n_read as u64;
         Ok(n_read)
     }
@@ -384,118 +970,969 @@ impl Seek for SparseBodyOpt {
     }
 }
 
-/// Struct representing the whole file from which a response was generated.
-///
-/// If the response contained the whole file, it contains the whole file.
-/// If the response was a 206 Partial, it contains a [rope](https://en.wikipedia.org/wiki/Rope_(data_structure))
-/// where the fetched parts are in the correct place as reported by the `Content-Range` header
-/// and the other parts are null bytes.
+/// A [Buf] over fetched data which spans several segments of a [SparseBody], without copying it.
 ///
-/// Implements [Read] and [Seek].
-pub struct SparseBody(SparseBodyOpt);
-
-impl SparseBody {
-    fn full(bytes: Bytes) -> Self {
-        SparseBody(SparseBodyOpt::Full(BytesRS::new(bytes)))
-    }
-
-    fn partial<T: IntoIterator<Item = ResponsePart>>(parts: T) -> Self {
-        make_sparse_body(parts)
-    }
+/// Returned by [SparseBody::get_buf].
+#[derive(Debug, Clone, Default)]
+pub struct ChainedBytes {
+    /// Non-empty chunks, in order.
+    chunks: VecDeque<Bytes>,
+    remaining: usize,
 }
 
-impl Read for SparseBody {
-    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        self.0.read(buf)
+impl ChainedBytes {
+    /// The remaining chunks, in order.
+    pub fn chunks(&self) -> impl Iterator<Item = &Bytes> {
+        self.chunks.iter()
     }
 }
 
-impl Seek for SparseBody {
-    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
-        self.0.seek(pos)
+impl Buf for ChainedBytes {
+    fn remaining(&self) -> usize {
+        self.remaining
     }
-}
 
-fn make_sparse_body<T: IntoIterator<Item = ResponsePart>>(parts: T) -> SparseBody {
-    let mut map = BTreeMap::default();
+    fn chunk(&self) -> &[u8] {
+        self.chunks.front().map_or(&[], |b| &b[..])
+    }
 
-    let mut total_len = 0;
-    // offset, len, part
-    for p in parts {
-        let Some((offset, len)) = p.offset_len() else {
-            continue
-        };
-        // If a content-range header knows the length of the full file, use that.
-        // Otherwise, infer end from the start and end of this byte range:
-        // as we process more ranges, this builds up towards the probable full length
-        if let Some(total) = p.total_size() {
-            total_len = total_len.max(total)
-        } else {
-            total_len = total_len.max(offset + len)
+    fn advance(&mut self, mut cnt: usize) {
+        assert!(
+            cnt <= self.remaining,
+            "cannot advance past the end: {cnt} > {}",
+            self.remaining
+        );
+        self.remaining -= cnt;
+        while let Some(front) = self.chunks.front_mut() {
+            if cnt < front.len() {
+                front.advance(cnt);
+                return;
+            }
+            cnt -= front.len();
+            self.chunks.pop_front();
         }
-        let tup = (offset, len, p);
+    }
 
-        // in case 2 parts start at the same offset, take the longer one
-        match map.entry(offset) {
-            Entry::Occupied(mut e) => {
-                let val: &mut (usize, usize, ResponsePart) = e.get_mut();
-                if val.1 < len {
-                    *val = tup;
-                }
+    /// Only copies if the bytes span more than one chunk.
+    fn copy_to_bytes(&mut self, len: usize) -> Bytes {
+        assert!(
+            len <= self.remaining,
+            "cannot copy past the end: {len} > {}",
+            self.remaining
+        );
+        match self.chunks.front_mut() {
+            Some(front) if len < front.len() => {
+                self.remaining -= len;
+                front.split_to(len)
             }
-            Entry::Vacant(e) => {
-                e.insert(tup);
+            Some(front) if len == front.len() => {
+                self.remaining -= len;
+                self.chunks.pop_front().unwrap_or_default()
+            }
+            _ => {
+                let mut out = BytesMut::with_capacity(len);
+                out.put(self.take(len));
+                out.freeze()
             }
         }
     }
+}
 
-    let mut nodes: Vec<Node<Part<BytesRS>>> = Vec::with_capacity(map.len() * 2 + 1);
-    let mut idx = 0;
-    for (offset, mut len, resp) in map.into_values().map(|(o, l, r)| (o as u64, l as u64, r)) {
-        if idx < offset {
-            let needed_len = offset - idx;
-            nodes.push(Node::leaf_with_length(Part::empty(needed_len), needed_len));
-            idx += needed_len;
-        }
-        let bytes = if idx > offset {
-            // in case of overlapping byte ranges
-            let remove_front = idx - offset;
-            if remove_front > len {
-                continue;
-            }
-            len -= remove_front;
-            resp.data.slice(remove_front as usize..)
-        } else {
-            resp.data.clone()
-        };
+/// Error from reading a region of a [SparseBody] in strict mode which was not fetched.
+///
+/// Returned as the inner error of an [io::Error] with kind [io::ErrorKind::Other];
+/// use [Unfetched::from_io] to get it back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+#[error("Byte {position} of the body was not fetched")]
+pub struct Unfetched {
+    pub position: usize,
+}
 
-        let brs = BytesRS::new(bytes);
+impl Unfetched {
+    /// Get the [Unfetched] error from an [io::Error] returned by a [SparseBody], if that was the cause.
+    pub fn from_io(err: &io::Error) -> Option<&Self> {
+        err.get_ref().and_then(|e| e.downcast_ref())
+    }
+}
 
-        nodes.push(Node::leaf_with_length(Part::Full(brs), len));
+/// Struct representing the whole file from which a response was generated.
+///
+/// If the response contained the whole file, it contains the whole file.
+/// If the response was a 206 Partial, it contains a [rope](https://en.wikipedia.org/wiki/Rope_(data_structure))
+/// where the fetched parts are in the correct place as reported by the `Content-Range` header
+/// and the other parts are null bytes.
+/// [SparseBody::populated] lists which parts were fetched.
+///
+/// Implements [Read] and [Seek];
+/// [SparseBody::get] and [SparseBody::get_buf] give fetched data without copying it.
+/// In strict mode (see [SparseBody::set_strict]), reading from a region which was not fetched
+/// returns an [Unfetched] error rather than null bytes.
+pub struct SparseBody {
+    /// Built from the segments on the first read or seek after data is added.
+    inner: SparseBodyOpt,
+    /// The position to restore in a new `inner`, if data has been added since it was built.
+    stale: Option<u64>,
+    /// Fetched data by offset: non-overlapping.
+    segments: BTreeMap<usize, Bytes>,
+    /// Offsets and lengths of fetched data: sorted, non-overlapping and non-adjacent.
+    populated: Vec<(usize, usize)>,
+    /// Complete length as reported by a response, if any.
+    complete_length: Option<usize>,
+    /// What each response whose data was added reported about the representation.
+    sources: Vec<Source>,
+    len: usize,
+    strict: bool,
+}
 
-        idx = offset + len;
-    }
-    let total_len_64 = total_len as u64;
-    if idx < total_len_64 {
-        let needed_len = total_len_64 - idx;
-        nodes.push(Node::leaf_with_length(Part::empty(needed_len), needed_len));
+impl Default for SparseBody {
+    fn default() -> Self {
+        Self {
+            inner: SparseBodyOpt::Full(BytesRS::new(Bytes::default())),
+            stale: None,
+            segments: BTreeMap::default(),
+            populated: Vec::default(),
+            complete_length: None,
+            sources: Vec::default(),
+            len: 0,
+            strict: false,
+        }
     }
-    let n = Node::partition_nodes(nodes);
-    SparseBody(SparseBodyOpt::Partial(n))
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::test_impl::{read_text, test_response};
+impl SparseBody {
+    /// An empty body, to be filled with [SparseBody::insert_response] or [SparseBody::insert_part].
+    pub fn new() -> Self {
+        Self::default()
+    }
 
-    #[test]
-    fn body_50_100() {
+    /// Add the whole representation, as from a 200 response.
+    ///
+    /// Data which is already held is kept, so this only fills the gaps.
+    pub fn insert_full(&mut self, bytes: Bytes) -> Result<(), SparseBodyError> {
+        let complete_length = merge_complete_length(self.complete_length, Some(bytes.len()))?;
+        self.check_end(complete_length, 0)?;
+        self.complete_length = complete_length;
+        self.insert_bytes(0, bytes);
+        self.invalidate()
+    }
+
+    /// Add a part of a 206 response.
+    ///
+    /// Data which is already held is kept, so where parts overlap, the part inserted first wins.
+    /// Parts whose `Content-Range` reports a different complete length to previous parts are rejected.
+    pub fn insert_part(&mut self, part: ResponsePart) -> Result<(), SparseBodyError> {
+        self.extend_parts([part])
+    }
+
+    /// Add a number of parts (see [SparseBody::insert_part]).
+    ///
+    /// Parts which start earlier, or are longer, take precedence over others in the same call.
+    /// If any part is rejected, none are added.
+    pub fn extend_parts<T: IntoIterator<Item = ResponsePart>>(
+        &mut self,
+        parts: T,
+    ) -> Result<(), SparseBodyError> {
+        let mut parts: Vec<_> = parts
+            .into_iter()
+            .filter_map(|p| Some((p.offset_len()?, p)))
+            .collect();
+        parts.sort_by_key(|((offset, len), _)| (*offset, std::cmp::Reverse(*len)));
+
+        let mut complete_length = self.complete_length;
+        let mut end = 0;
+        for ((offset, len), p) in parts.iter() {
+            complete_length = merge_complete_length(complete_length, p.total_size())?;
+            end = end.max(offset + (*len).min(p.data.len()));
+        }
+        self.check_end(complete_length, end)?;
+        self.complete_length = complete_length;
+
+        for (_, p) in parts {
+            self.insert_part_inner(p);
+        }
+        self.invalidate()
+    }
+
+    /// Add the contents of another response (see [SparseBody::insert_part]).
+    ///
+    /// If the response was complete, the whole representation is added.
+    ///
+    /// If the response's `ETag` or `Last-Modified` conflicts with that of an earlier response,
+    /// the representation has changed and a [SparseBodyError::ValidatorMismatch] is returned;
+    /// if its complete length does, a [SparseBodyError::LengthMismatch].
+    /// In either case, nothing is added.
+    pub fn insert_response<R: MaybePartialResponse>(
+        &mut self,
+        response: R,
+    ) -> Result<(), SparseBodyError<R::Error>> {
+        let source = Source::from_response(&response);
+        let part_desc = match response.status_code() {
+            200 => None,
+            _ => Some(response.part_description().map_err(|e| e.widen())?),
+        };
+        let body = response.body().map_err(SparseBodyError::Body)?;
+        self.insert_body(source, part_desc, body)
+            .map_err(|e| e.widen())
+    }
+
+    /// Add a response's body, which is the whole representation if there is no `part_desc`
+    /// (i.e. the response was a 200), otherwise its parts.
+    pub(crate) fn insert_body(
+        &mut self,
+        source: Source,
+        part_desc: Option<PartDesc>,
+        body: Bytes,
+    ) -> Result<(), SparseBodyError> {
+        let Some(part_desc) = part_desc else {
+            return self.insert_full_from(source, body);
+        };
+        let pv: Result<Vec<ResponsePart>, PartParseError> = Parts::new(part_desc, body).collect();
+        self.extend_parts_from(source, pv?)
+    }
+
+    /// As [SparseBody::insert_full], recording the response it came from.
+    pub(crate) fn insert_full_from(
+        &mut self,
+        mut source: Source,
+        bytes: Bytes,
+    ) -> Result<(), SparseBodyError> {
+        self.check_source(&source)?;
+        source.complete_length = Some(bytes.len());
+        self.insert_full(bytes)?;
+        self.sources.push(source);
+        Ok(())
+    }
+
+    /// As [SparseBody::extend_parts], recording the response they came from.
+    pub(crate) fn extend_parts_from(
+        &mut self,
+        mut source: Source,
+        parts: Vec<ResponsePart>,
+    ) -> Result<(), SparseBodyError> {
+        self.check_source(&source)?;
+        source.complete_length = parts.iter().find_map(|p| p.total_size());
+        self.extend_parts(parts)?;
+        self.sources.push(source);
+        Ok(())
+    }
+
+    /// What each response whose data was added reported about the representation, in order.
+    pub fn sources(&self) -> &[Source] {
+        &self.sources
+    }
+
+    /// The validator of the representation, as reported by the first response which had one.
+    pub fn validator(&self) -> Option<Validator> {
+        self.sources.iter().find_map(|s| s.validator())
+    }
+
+    fn check_source(&self, source: &Source) -> Result<(), SparseBodyError> {
+        for existing in self.sources.iter() {
+            if let Some((expected, found)) = existing.conflict(source) {
+                return Err(SparseBodyError::ValidatorMismatch {
+                    expected,
+                    found: Some(found),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that neither the data held nor new data ending at `end` goes beyond the complete length.
+    fn check_end(&self, complete_length: Option<usize>, end: usize) -> Result<(), SparseBodyError> {
+        let held_end = self.populated.last().map_or(0, |(o, l)| o + l);
+        match complete_length {
+            Some(complete_length) if end.max(held_end) > complete_length => {
+                Err(SparseBodyError::OutOfBounds {
+                    end: end.max(held_end),
+                    complete_length,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn insert_part_inner(&mut self, part: ResponsePart) {
+        let Some((offset, len)) = part.offset_len() else {
+            return;
+        };
+        let data = part.data.slice(..len.min(part.data.len()));
+        self.insert_bytes(offset, data);
+    }
+
+    /// Insert the bytes into the gaps between existing segments.
+    fn insert_bytes(&mut self, offset: usize, data: Bytes) {
+        let end = offset + data.len();
+        let mut idx = offset;
+        // a segment starting before the offset may cover its start
+        if let Some((o, d)) = self.segments.range(..offset).next_back() {
+            idx = idx.max(o + d.len());
+        }
+        let following: Vec<_> = self
+            .segments
+            .range(offset..end)
+            .map(|(o, d)| (*o, d.len()))
+            .collect();
+        let mut new = Vec::default();
+        for (o, l) in following {
+            if idx < o {
+                new.push((idx, data.slice(idx - offset..o - offset)));
+            }
+            idx = idx.max(o + l);
+        }
+        if idx < end {
+            new.push((idx, data.slice(idx - offset..)));
+        }
+        self.segments.extend(new);
+        if offset < end {
+            self.populate(offset, end);
+        }
+    }
+
+    /// Merge the span from `start` to `end` into the populated spans,
+    /// joining any which it overlaps or touches.
+    fn populate(&mut self, start: usize, end: usize) {
+        let first = self.populated.partition_point(|(o, l)| o + l < start);
+        let last = self.populated.partition_point(|(o, _)| *o <= end);
+        let (mut start, mut end) = (start, end);
+        if first < last {
+            start = start.min(self.populated[first].0);
+            let (o, l) = self.populated[last - 1];
+            end = end.max(o + l);
+        }
+        self.populated.splice(first..last, [(start, end - start)]);
+    }
+
+    /// Update the length after data is added, and mark the rope to be rebuilt when next used.
+    ///
+    /// Rebuilding lazily means that a number of insertions only costs one rebuild.
+    fn invalidate(&mut self) -> Result<(), SparseBodyError> {
+        let data_end = self.populated.last().map_or(0, |(o, l)| o + l);
+        // If a content-range header knows the length of the full file, use that.
+        // Otherwise, infer it from the furthest byte we have.
+        self.len = self.complete_length.unwrap_or(0).max(data_end);
+        if self.stale.is_none() {
+            self.stale = Some(self.inner.stream_position()?);
+        }
+        Ok(())
+    }
+
+    /// The rope, rebuilt from the segments if data has been added since it was last used,
+    /// keeping the current position.
+    fn inner(&mut self) -> io::Result<&mut SparseBodyOpt> {
+        if let Some(position) = self.stale.take() {
+            self.inner = match self.segments.first_key_value() {
+                Some((0, data)) if data.len() == self.len => {
+                    SparseBodyOpt::Full(BytesRS::new(data.clone()))
+                }
+                _ => SparseBodyOpt::Partial(self.make_rope()),
+            };
+            self.inner.seek(SeekFrom::Start(position))?;
+        }
+        Ok(&mut self.inner)
+    }
+
+    fn make_rope(&self) -> Node<Part<BytesRS>> {
+        let mut nodes: Vec<Node<Part<BytesRS>>> = Vec::with_capacity(self.segments.len() * 2 + 1);
+        let mut idx = 0;
+        for (offset, data) in self.segments.iter() {
+            let (offset, len) = (*offset as u64, data.len() as u64);
+            if idx < offset {
+                let needed_len = offset - idx;
+                nodes.push(Node::leaf_with_length(Part::empty(needed_len), needed_len));
+            }
+            nodes.push(Node::leaf_with_length(
+                Part::Full(BytesRS::new(data.clone())),
+                len,
+            ));
+            idx = offset + len;
+        }
+        let total_len = self.len as u64;
+        if idx < total_len {
+            let needed_len = total_len - idx;
+            nodes.push(Node::leaf_with_length(Part::empty(needed_len), needed_len));
+        }
+        Node::partition_nodes(nodes)
+    }
+
+    /// Total length of the body.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Offsets and lengths of the spans which contain fetched data, in order.
+    ///
+    /// Adjacent spans are merged.
+    pub fn populated(&self) -> &[(usize, usize)] {
+        &self.populated
+    }
+
+    /// Whether every byte in the given range was fetched.
+    ///
+    /// Unbounded ranges extend to the start or end of the body.
+    pub fn contains<R: RangeBounds<usize>>(&self, range: R) -> bool {
+        let (start, end) = self.bounds(range);
+        if start >= end {
+            return true;
+        }
+        self.span_at(start)
+            .is_some_and(|(offset, len)| end <= offset + len)
+    }
+
+    /// The fetched data in the given range, if it lies within a single inserted segment.
+    ///
+    /// This is a cheap slice of the response's [Bytes], not a copy.
+    /// Returns [None] if any of the range was not fetched,
+    /// or if it spans data from more than one segment (see [SparseBody::get_buf]).
+    /// Unbounded ranges extend to the start or end of the body.
+    ///
+    /// ```rust
+    /// # use byteranges::response::{Bytes, SparseBody};
+    /// let mut body = SparseBody::new();
+    /// body.insert_full(Bytes::from_static(b"hello world")).unwrap();
+    /// assert_eq!(body.get(6..).unwrap(), "world");
+    /// ```
+    pub fn get<R: RangeBounds<usize>>(&self, range: R) -> Option<Bytes> {
+        let (start, end) = self.bounds(range);
+        if start >= end {
+            return Some(Bytes::new());
+        }
+        let (offset, data) = self.segments.range(..=start).next_back()?;
+        (end <= offset + data.len()).then(|| data.slice(start - offset..end - offset))
+    }
+
+    /// The fetched data in the given range as a [Buf] over every segment it spans, without copying.
+    ///
+    /// Returns [None] if any of the range was not fetched.
+    /// Unbounded ranges extend to the start or end of the body.
+    pub fn get_buf<R: RangeBounds<usize>>(&self, range: R) -> Option<ChainedBytes> {
+        let (start, end) = self.bounds(range);
+        let mut out = ChainedBytes::default();
+        if start >= end {
+            return Some(out);
+        }
+        let (first, _) = self.segments.range(..=start).next_back()?;
+        let mut idx = start;
+        for (offset, data) in self.segments.range(first..) {
+            if *offset > idx {
+                return None;
+            }
+            let chunk_end = end.min(offset + data.len());
+            if chunk_end <= idx {
+                continue;
+            }
+            out.chunks
+                .push_back(data.slice(idx - offset..chunk_end - offset));
+            out.remaining += chunk_end - idx;
+            idx = chunk_end;
+            if idx == end {
+                return Some(out);
+            }
+        }
+        None
+    }
+
+    /// Resolve a range against the body's length.
+    fn bounds<R: RangeBounds<usize>>(&self, range: R) -> (usize, usize) {
+        use std::ops::Bound::*;
+        let start = match range.start_bound() {
+            Included(i) => *i,
+            Excluded(i) => i + 1,
+            Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Included(i) => i + 1,
+            Excluded(i) => *i,
+            Unbounded => self.len,
+        };
+        (start, end)
+    }
+
+    /// A [RangeHeader] for the bytes within `wanted` which have not been fetched yet.
+    ///
+    /// The header is empty if everything wanted is held.
+    /// If no response has reported the complete length and `wanted` is unbounded,
+    /// the final range is open-ended.
+    ///
+    /// ```rust
+    /// # use byteranges::{request::MissingRanges, response::SparseBody};
+    /// let body = SparseBody::new();
+    /// let header = body.missing(100.., MissingRanges::new().max_ranges(4));
+    /// assert_eq!(header.to_string(), "bytes=100-");
+    /// ```
+    pub fn missing<R: RangeBounds<usize>>(
+        &self,
+        wanted: R,
+        options: &MissingRanges,
+    ) -> RangeHeader<'static> {
+        options.header(self.populated.iter().copied(), wanted, self.complete_length)
+    }
+
+    /// The populated span containing the given position.
+    fn span_at(&self, position: usize) -> Option<(usize, usize)> {
+        let idx = self
+            .populated
+            .partition_point(|(offset, _)| *offset <= position);
+        let (offset, len) = *self.populated.get(idx.checked_sub(1)?)?;
+        (position < offset + len).then_some((offset, len))
+    }
+
+    /// Set whether reads from regions which were not fetched should fail with [Unfetched],
+    /// rather than returning null bytes.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    pub fn is_strict(&self) -> bool {
+        self.strict
+    }
+}
+
+impl Read for SparseBody {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if !self.strict {
+            return self.inner()?.read(buf);
+        }
+        let position = self.inner()?.stream_position()? as usize;
+        if position >= self.len || buf.is_empty() {
+            return Ok(0);
+        }
+        let Some((offset, len)) = self.span_at(position) else {
+            return Err(io::Error::other(Unfetched { position }));
+        };
+        // stop at the end of the span, so that the next read fails
+        let n = buf.len().min(offset + len - position);
+        self.inner()?.read(&mut buf[..n])
+    }
+}
+
+impl Seek for SparseBody {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner()?.seek(pos)
+    }
+}
+
+/// A response made by a [Representation].
+#[derive(Debug, Clone)]
+pub struct RangeResponse {
+    pub(crate) status: u16,
+    pub(crate) headers: Vec<(String, Bytes)>,
+    pub(crate) body: Bytes,
+}
+
+impl RangeResponse {
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    /// All headers, in order.
+    pub fn headers(&self) -> &[(String, Bytes)] {
+        &self.headers
+    }
+
+    /// The value of the first header with the given name, case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&[u8]> {
+        find_header(&self.headers, name)
+    }
+
+    /// The body, without consuming the response.
+    pub fn data(&self) -> &Bytes {
+        &self.body
+    }
+}
+
+impl MaybePartialResponse for RangeResponse {
+    type Error = Infallible;
+
+    fn status_code(&self) -> u16 {
+        self.status
+    }
+
+    fn header_values(&self, name: &str) -> Vec<&str> {
+        self.headers
+            .iter()
+            .filter(|(n, _)| n.eq_ignore_ascii_case(name))
+            .filter_map(|(_, v)| std::str::from_utf8(v).ok())
+            .collect()
+    }
+
+    fn body(self) -> Result<Bytes, Infallible> {
+        Ok(self.body)
+    }
+}
+
+/// A representation held in memory, which answers range requests
+/// as a server following [RFC 9110 §14](https://www.rfc-editor.org/rfc/rfc9110#section-14) would.
+///
+/// Satisfiable ranges get a 206 response, which is `multipart/byteranges` if there is more than one;
+/// a `Range` with no satisfiable ranges gets a 416 with the complete length,
+/// and a request without a `Range`, or whose `If-Range` does not match, gets the whole representation.
+///
+/// This is what [MockClient](crate::client::MockClient), [TestServer](crate::test_server::TestServer)
+/// and [RangeResponder] serve.
+///
+/// ```rust
+/// # use byteranges::response::{Bytes, MaybePartialResponse, Representation};
+/// let representation = Representation::new(Bytes::from_static(b"0123456789"));
+/// let response = representation.respond(&(2..5).into(), None).unwrap();
+/// assert_eq!(response.status_code(), 206);
+/// assert_eq!(response.content_range_str(), Some("bytes 2-4/10"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct Representation {
+    data: Bytes,
+    content_type: String,
+    etag: Option<EntityTag>,
+    last_modified: Option<SystemTime>,
+}
+
+impl Representation {
+    pub fn new(data: Bytes) -> Self {
+        Self {
+            data,
+            content_type: "application/octet-stream".to_owned(),
+            etag: None,
+            last_modified: None,
+        }
+    }
+
+    pub fn data(&self) -> &Bytes {
+        &self.data
+    }
+
+    pub fn set_data(&mut self, data: Bytes) -> &mut Self {
+        self.data = data;
+        self
+    }
+
+    pub fn set_content_type<S: Into<String>>(&mut self, content_type: S) -> &mut Self {
+        self.content_type = content_type.into();
+        self
+    }
+
+    pub fn set_etag(&mut self, etag: Option<EntityTag>) -> &mut Self {
+        self.etag = etag;
+        self
+    }
+
+    /// Set the modification date, truncated to whole seconds as it would be in a `Last-Modified` header.
+    pub fn set_last_modified(&mut self, last_modified: Option<SystemTime>) -> &mut Self {
+        self.last_modified = last_modified.map(|t| {
+            let secs = t
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs());
+            SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+        });
+        self
+    }
+
+    /// The response to a `GET` request with the given `Range` (which may be empty) and `If-Range`.
+    ///
+    /// Fails only if a `multipart/byteranges` body cannot be built for the content type.
+    pub fn respond(
+        &self,
+        range: &RangeHeader,
+        if_range: Option<&IfRange>,
+    ) -> Result<RangeResponse, MultipartError> {
+        let len = self.data.len();
+        let full = || {
+            self.response(
+                200,
+                vec![(CONTENT_TYPE, self.content_type.clone())],
+                self.data.clone(),
+            )
+        };
+        if range.is_empty()
+            || range.unit() != BYTES
+            || if_range.is_some_and(|ir| !self.if_range_matches(ir))
+        {
+            return Ok(full());
+        }
+        // a suffix of an empty representation selects nothing, which a Content-Range cannot describe
+        let spans: Vec<_> = match range.resolve(len) {
+            Ok(spans) => spans.into_iter().filter(|(_, l)| *l > 0).collect(),
+            Err(_) => Vec::default(),
+        };
+        let response = match spans[..] {
+            [] => self.response(
+                416,
+                vec![(CONTENT_RANGE, format!("bytes */{len}"))],
+                Bytes::default(),
+            ),
+            [(offset, l)] => self.response(
+                206,
+                vec![
+                    (CONTENT_TYPE, self.content_type.clone()),
+                    (
+                        CONTENT_RANGE,
+                        format!("bytes {offset}-{}/{len}", offset + l - 1),
+                    ),
+                ],
+                self.data.slice(offset..offset + l),
+            ),
+            _ => {
+                let body = MultipartBuilder::new(self.content_type.clone())
+                    .complete_length(len)
+                    .extend(spans.iter().map(|&(o, l)| (o, self.data.slice(o..o + l))))
+                    .build()?;
+                self.response(
+                    206,
+                    vec![(CONTENT_TYPE, body.content_type())],
+                    body.to_bytes(),
+                )
+            }
+        };
+        Ok(response)
+    }
+
+    fn if_range_matches(&self, if_range: &IfRange) -> bool {
+        let validator = match if_range.validator() {
+            Validator::ETag(_) => self.etag.clone().map(Validator::ETag),
+            Validator::LastModified(_) => self.last_modified.map(Validator::LastModified),
+        };
+        validator.is_some_and(|v| if_range.matches(&v))
+    }
+
+    fn response(&self, status: u16, headers: Vec<(&str, String)>, body: Bytes) -> RangeResponse {
+        let mut all = vec![("Accept-Ranges", BYTES.to_owned())];
+        if let Some(etag) = &self.etag {
+            all.push((ETAG, etag.to_string()));
+        }
+        if let Some(t) = self.last_modified {
+            all.push((LAST_MODIFIED, fmt_http_date(t)));
+        }
+        all.extend(headers);
+        all.push(("Content-Length", body.len().to_string()));
+        RangeResponse {
+            status,
+            headers: all
+                .into_iter()
+                .map(|(n, v)| (n.to_owned(), Bytes::from(v)))
+                .collect(),
+            body,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::multipart::MultipartBuilder;
+    use crate::test_impl::{malformed_bodies, read_text, test_fixture, test_response};
+    use proptest::prelude::*;
+
+    fn multi_desc(content_type: &str) -> Result<PartDesc, PartialHeaderParseError> {
+        part_description(206, Some(content_type), None)
+    }
+
+    #[test]
+    fn boundary_params() {
+        for (ct, boundary) in [
+            ("multipart/byteranges; boundary=abc", "abc"),
+            ("multipart/byteranges; charset=x; boundary=\"abc\"", "abc"),
+            ("multipart/byteranges;boundary=\"a b\";charset=x", "a b"),
+            ("Multipart/ByteRanges ; BOUNDARY = abc", "abc"),
+            ("multipart/byteranges; boundary=\"a\\bc\"", "abc"),
+        ] {
+            let PartDesc::Multi { boundary: b } = multi_desc(ct).unwrap() else {
+                panic!("not multipart: {ct:?}");
+            };
+            assert_eq!(b, format!("--{boundary}").into_bytes(), "{ct:?}");
+        }
+    }
+
+    #[test]
+    fn boundary_errors() {
+        assert!(matches!(
+            multi_desc("multipart/byteranges"),
+            Err(PartialHeaderParseError::NoBoundary)
+        ));
+        assert!(matches!(
+            multi_desc("multipart/byteranges; charset=x"),
+            Err(PartialHeaderParseError::NoBoundary)
+        ));
+        assert!(matches!(
+            multi_desc("multipart/byteranges; boundary=\"\""),
+            Err(PartialHeaderParseError::InvalidBoundary(_))
+        ));
+        assert!(matches!(
+            multi_desc("multipart/byteranges; boundary=\"a;b\""),
+            Err(PartialHeaderParseError::InvalidBoundary(_))
+        ));
+        assert!(matches!(
+            multi_desc("multipart/byteranges; boundary=\"abc"),
+            Err(PartialHeaderParseError::ContentTypeParse(_))
+        ));
+    }
+
+    #[test]
+    fn multipart_styles() {
+        let reference = read_text();
+        for (fname, spans) in [
+            ("numeric", &[(0, 50), (1000, 100), (4000, 57)][..]),
+            ("hex", &[(0, 50), (1000, 100), (4000, 57)]),
+            ("quoted", &[(0, 50), (1000, 100), (4000, 57)]),
+            ("long", &[(0, 50), (1000, 100), (4000, 57)]),
+            ("params", &[(0, 50), (1000, 100), (4000, 57)]),
+        ] {
+            test_fixture("multipart", fname, |resp| {
+                let bod = resp.sparse_body().unwrap();
+                assert_eq!(bod.len(), reference.len(), "{fname}");
+                assert_eq!(bod.populated(), spans, "{fname}");
+            });
+            test_fixture("multipart", fname, |resp| {
+                for part in resp.parts().unwrap() {
+                    let part = part.unwrap();
+                    let (offset, len) = part.offset_len().unwrap();
+                    assert_eq!(part.data(), &reference[offset..offset + len], "{fname}");
+                }
+            });
+        }
+    }
+
+    fn fixture_parts(fname: &str, lenient: bool) -> Result<Vec<ResponsePart>, PartParseError> {
+        test_fixture("multipart", fname, |resp| {
+            let mut parts = resp.parts().unwrap();
+            parts.set_lenient(lenient);
+            parts.collect()
+        })
+    }
+
+    #[test]
+    fn lenient_fixtures() {
+        let reference = read_text();
+        for (fname, strict_ok) in [
+            ("preamble", true),
+            ("epilogue", true),
+            ("padding", false),
+            ("lf", false),
+        ] {
+            let strict = fixture_parts(fname, false);
+            assert_eq!(strict.is_ok(), strict_ok, "{fname}");
+            let parts = fixture_parts(fname, true).unwrap();
+            assert_eq!(parts.len(), 3, "{fname}");
+            for part in parts {
+                let (offset, len) = part.offset_len().unwrap();
+                assert_eq!(part.data(), &reference[offset..offset + len], "{fname}");
+            }
+        }
+    }
+
+    #[test]
+    fn lenient_data_ending_in_cr() {
+        let body = b"--B\nContent-Type: a\nContent-Range: bytes 0-2/3\n\nab\r\n--B--\n";
+        let part_desc = PartDesc::Multi {
+            boundary: b"--B".to_vec(),
+        };
+        let mut parts = Parts::new(part_desc, Bytes::from_static(body));
+        let part = parts.set_lenient(true).next().unwrap().unwrap();
+        assert_eq!(part.data(), &b"ab\r"[..]);
+        assert!(parts.next().is_none());
+    }
+
+    #[test]
+    fn many_part_headers() {
+        let mut head = String::from("--B\r\nContent-Type: text/plain\r\n");
+        for i in 0..100 {
+            head.push_str(&format!("X-Trace-{i}: {i}\r\n"));
+        }
+        head.push_str("ETag: \"abc\"\r\nContent-Range: bytes 0-2/3\r\n\r\n");
+        let body = format!("{head}abc\r\n--B--\r\n");
+        let part_desc = PartDesc::Multi {
+            boundary: b"--B".to_vec(),
+        };
+        let part = Parts::new(part_desc, body.into()).next().unwrap().unwrap();
+        assert_eq!(part.data(), &b"abc"[..]);
+        assert_eq!(part.headers().len(), 103);
+        assert_eq!(part.headers()[1].0, "X-Trace-0");
+        assert_eq!(part.header("etag"), Some(&b"\"abc\""[..]));
+        assert_eq!(part.header("x-trace-99"), Some(&b"99"[..]));
+        assert_eq!(part.header("content-encoding"), None);
+    }
+
+    #[test]
+    fn malformed_parts() {
+        for (body, expected) in malformed_bodies() {
+            let part_desc = PartDesc::Multi {
+                boundary: b"--B".to_vec(),
+            };
+            let mut parts = Parts::new(part_desc, body.into());
+            let err = parts.find_map(|p| p.err());
+            assert_eq!(err.as_ref(), Some(&expected));
+            assert!(parts.next().is_none());
+        }
+    }
+
+    #[test]
+    fn invalid_boundaries() {
+        for boundary in [&b""[..], b"--", b"B", b"--B\r\n", b"--\xff"] {
+            let part_desc = PartDesc::Multi {
+                boundary: boundary.to_vec(),
+            };
+            let mut parts = Parts::new(part_desc, Bytes::from_static(b"--B\r\n"));
+            let err = parts.next().unwrap().unwrap_err();
+            assert_eq!(err.kind, PartParseErrorKind::InvalidBoundary);
+            assert!(parts.next().is_none());
+        }
+    }
+
+    #[test]
+    fn single_part_length_mismatch() {
+        let part_desc = PartDesc::Single {
+            content_range: ContentRange::parse_bytes(b"bytes 10-19/100"),
+            content_type: "text/plain".to_owned(),
+        };
+        let err = Parts::new(part_desc, Bytes::from_static(b"short"))
+            .next()
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(
+            err.kind,
+            PartParseErrorKind::LengthMismatch {
+                expected: 10,
+                found: 5
+            }
+        );
+    }
+
+    #[test]
+    fn single_part_content_types() {
+        // object stores answer single ranges with whatever type the object was stored as
+        for content_type in [
+            "binary/octet-stream",
+            "application/octet-stream",
+            "text/plain; charset=utf-8",
+            "multipart/mixed; boundary=B",
+            "not a media type",
+        ] {
+            let desc = part_description(206, Some(content_type), Some("bytes 0-9/100")).unwrap();
+            match desc {
+                PartDesc::Single {
+                    content_type: ct, ..
+                } => assert_eq!(ct, content_type),
+                PartDesc::Multi { .. } => panic!("{content_type} treated as multipart"),
+            }
+        }
+    }
+
+    #[test]
+    fn huge_content_range() {
+        let cr = ContentRange::parse_bytes(b"bytes 0-18446744073709551615/*");
+        assert!(matches!(cr, ContentRange::UnboundBytes(_)));
+        assert_eq!(offset_len(&cr), None);
+    }
+
+    #[test]
+    fn body_50_100() {
         let reference = read_text();
         test_response("bytes=50-100", |resp| {
             let mut bod = resp.sparse_body().unwrap();
             let mut buf = [255; 150];
-            bod.read(&mut buf).unwrap();
+            bod.read_exact(&mut buf).unwrap();
             assert_eq!(buf[..50], [0; 50]);
             assert_eq!(buf[50..=100], reference[50..=100]);
             assert_eq!(buf[101..150], [0; 49]);
@@ -509,7 +1946,7 @@ mod tests {
             let mut bod = resp.sparse_body().unwrap();
             let mut buf = [255; 200];
             bod.seek(SeekFrom::Start(2900)).unwrap();
-            bod.read(&mut buf).unwrap();
+            bod.read_exact(&mut buf).unwrap();
             assert_eq!(buf[..100], [0; 100]);
             assert_eq!(buf[100..], reference[3000..3100]);
         });
@@ -528,4 +1965,575 @@ mod tests {
             assert_eq!(buf[100..], reference[reference.len() - 100..]);
         });
     }
+
+    #[test]
+    fn populated_spans() {
+        test_response("bytes=50-100", |resp| {
+            let bod = resp.sparse_body().unwrap();
+            assert_eq!(bod.len(), 4057);
+            assert_eq!(bod.populated(), &[(50, 51)]);
+            assert!(bod.contains(50..=100));
+            assert!(bod.contains(60..70));
+            assert!(!bod.contains(49..60));
+            assert!(!bod.contains(90..102));
+            assert!(!bod.contains(..));
+        });
+    }
+
+    #[test]
+    fn strict_read() {
+        let reference = read_text();
+        test_response("bytes=50-100", |resp| {
+            let mut bod = resp.sparse_body().unwrap();
+            bod.set_strict(true);
+
+            let err = bod.read(&mut [0; 10]).unwrap_err();
+            assert_eq!(Unfetched::from_io(&err), Some(&Unfetched { position: 0 }));
+
+            bod.seek(SeekFrom::Start(90)).unwrap();
+            let mut buf = [0; 20];
+            assert_eq!(bod.read(&mut buf).unwrap(), 11);
+            assert_eq!(buf[..11], reference[90..=100]);
+            let err = bod.read(&mut buf).unwrap_err();
+            assert_eq!(Unfetched::from_io(&err), Some(&Unfetched { position: 101 }));
+
+            bod.seek(SeekFrom::End(0)).unwrap();
+            assert_eq!(bod.read(&mut buf).unwrap(), 0);
+        });
+    }
+
+    #[test]
+    fn full_body_populated() {
+        let mut bod = full(Bytes::from_static(b"abcdef"));
+        bod.set_strict(true);
+        assert_eq!(bod.populated(), &[(0, 6)]);
+        assert!(bod.contains(..));
+        let mut buf = Vec::default();
+        bod.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, b"abcdef");
+    }
+
+    #[test]
+    fn get_slices() {
+        let reference = read_text();
+        test_response("bytes=50-100", |resp| {
+            let bod = resp.sparse_body().unwrap();
+            let data = bod.get(60..70).unwrap();
+            assert_eq!(data, reference[60..70]);
+            assert_eq!(bod.get(50..=100).unwrap(), reference[50..=100]);
+            assert_eq!(bod.get(60..60).unwrap(), Bytes::new());
+            assert_eq!(bod.get(49..60), None);
+            assert_eq!(bod.get(90..102), None);
+            assert_eq!(bod.get(..), None);
+        });
+    }
+
+    #[test]
+    fn get_across_segments() {
+        let bod = partial([
+            part(0, b"abc", None),
+            part(3, b"def", None),
+            part(7, b"h", None),
+        ])
+        .unwrap();
+        assert_eq!(bod.populated(), &[(0, 6), (7, 1)]);
+        assert_eq!(bod.get(1..3).unwrap(), "bc");
+        assert_eq!(bod.get(1..5), None);
+
+        let mut buf = bod.get_buf(1..5).unwrap();
+        assert_eq!(buf.remaining(), 4);
+        assert_eq!(buf.chunks().collect::<Vec<_>>(), ["bc", "de"]);
+        assert_eq!(buf.chunk(), b"bc");
+        buf.advance(3);
+        assert_eq!(buf.chunk(), b"e");
+        assert_eq!(buf.remaining(), 1);
+
+        assert_eq!(bod.get_buf(..6).unwrap().copy_to_bytes(6), "abcdef");
+        assert_eq!(bod.get_buf(4..4).unwrap().remaining(), 0);
+        assert!(bod.get_buf(5..8).is_none());
+        assert!(bod.get_buf(..).is_none());
+    }
+
+    #[test]
+    fn chained_copy_to_bytes() {
+        let bod = partial([part(0, b"abc", None), part(3, b"def", None)]).unwrap();
+        let mut buf = bod.get_buf(..).unwrap();
+        let first = buf.copy_to_bytes(2);
+        assert_eq!(first, "ab");
+        // a slice of the inserted data rather than a copy
+        assert_eq!(first.as_ptr(), bod.get(0..1).unwrap().as_ptr());
+        assert_eq!(buf.copy_to_bytes(1), "c");
+        assert_eq!(buf.copy_to_bytes(3), "def");
+        assert!(!buf.has_remaining());
+    }
+
+    fn full(bytes: Bytes) -> SparseBody {
+        let mut body = SparseBody::new();
+        body.insert_full(bytes).unwrap();
+        body
+    }
+
+    fn partial<T: IntoIterator<Item = ResponsePart>>(
+        parts: T,
+    ) -> Result<SparseBody, SparseBodyError> {
+        let mut body = SparseBody::new();
+        body.extend_parts(parts)?;
+        Ok(body)
+    }
+
+    fn part(offset: usize, data: &'static [u8], complete_length: Option<usize>) -> ResponsePart {
+        let total = complete_length.map_or("*".to_owned(), |l| l.to_string());
+        let cr = format!("bytes {offset}-{}/{total}", offset + data.len() - 1);
+        ResponsePart::new(
+            "text/plain".to_owned(),
+            ContentRange::parse(&cr),
+            Bytes::from_static(data),
+        )
+    }
+
+    #[test]
+    fn merge_responses() {
+        let reference = read_text();
+        let mut bod = SparseBody::new();
+        test_response("bytes=50-100", |resp| bod.insert_response(resp).unwrap());
+        test_response("bytes=-100", |resp| bod.insert_response(resp).unwrap());
+        test_response("bytes=3000-", |resp| bod.insert_response(resp).unwrap());
+        assert_eq!(bod.len(), reference.len());
+        assert_eq!(bod.populated(), &[(50, 51), (3000, 1057)]);
+
+        let mut buf = Vec::default();
+        bod.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf[50..=100], reference[50..=100]);
+        assert_eq!(buf[101..3000], vec![0; 2899]);
+        assert_eq!(buf[3000..], reference[3000..]);
+    }
+
+    #[test]
+    fn merge_keeps_position() {
+        let mut bod = partial([part(0, b"abc", None)]).unwrap();
+        let mut buf = [0; 2];
+        bod.read_exact(&mut buf).unwrap();
+        bod.insert_part(part(4, b"efg", None)).unwrap();
+        let mut buf = Vec::default();
+        bod.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, b"c\0efg");
+    }
+
+    #[test]
+    fn merge_many_parts() {
+        let text = Bytes::from(read_text());
+        let mut bod = SparseBody::new();
+        // every other 10-byte block, then the rest in reverse, so that spans join up from both sides
+        let blocks: Vec<_> = (0..text.len()).step_by(10).collect();
+        let (evens, odds): (Vec<usize>, Vec<usize>) = blocks.into_iter().partition(|o| o % 20 == 0);
+        for offset in evens.into_iter().chain(odds.into_iter().rev()) {
+            let end = (offset + 10).min(text.len());
+            bod.insert_bytes(offset, text.slice(offset..end));
+            bod.invalidate().unwrap();
+            assert!(bod.contains(offset..end));
+        }
+        assert_eq!(bod.populated(), &[(0, text.len())]);
+        let mut buf = Vec::default();
+        bod.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, text);
+    }
+
+    #[test]
+    fn merge_overlap_keeps_held_data() {
+        let mut bod = partial([part(2, b"cde", Some(10))]).unwrap();
+        bod.insert_part(part(0, b"ABCDEFG", Some(10))).unwrap();
+        assert_eq!(bod.populated(), &[(0, 7)]);
+        let mut buf = Vec::default();
+        bod.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, b"ABcdeFG\0\0\0");
+    }
+
+    #[test]
+    fn merge_conflicting_length() {
+        let mut bod = partial([part(0, b"abc", Some(10))]).unwrap();
+        let err = bod.insert_part(part(5, b"fgh", Some(11))).unwrap_err();
+        assert!(matches!(
+            err,
+            SparseBodyError::LengthMismatch {
+                expected: 10,
+                found: 11
+            }
+        ));
+        let err = bod
+            .insert_full(Bytes::from_static(b"abcdefghijk"))
+            .unwrap_err();
+        assert!(matches!(err, SparseBodyError::LengthMismatch { .. }));
+    }
+
+    #[test]
+    fn unsatisfied() {
+        test_fixture("handmade", "unsatisfiable", |resp| {
+            let err = resp.part_description().unwrap_err();
+            assert!(matches!(
+                err,
+                PartialHeaderParseError::Unsatisfied {
+                    complete_length: Some(4057)
+                }
+            ));
+            assert_eq!(err.unsatisfied_length(), Some(4057));
+        });
+        let err = part_description(416, None, Some("bytes 0-10/*")).unwrap_err();
+        assert!(matches!(
+            err,
+            PartialHeaderParseError::Unsatisfied {
+                complete_length: None
+            }
+        ));
+        // a 206 response must not have one
+        let err = part_description(206, Some("text/plain"), Some("bytes */4057")).unwrap_err();
+        assert!(matches!(
+            err,
+            PartialHeaderParseError::UnsatisfiedContentRange(_)
+        ));
+    }
+
+    #[test]
+    fn single_values() {
+        assert_eq!(single_value("A", vec![]).unwrap(), None);
+        assert_eq!(single_value("A", vec!["a", " a"]).unwrap(), Some("a"));
+        let err = single_value("A", vec!["a", "b"]).unwrap_err();
+        assert!(matches!(
+            err,
+            PartialHeaderParseError::ConflictingHeaders { name, values } if name == "A" && values == ["a", "b"]
+        ));
+    }
+
+    #[test]
+    fn errors_are_send_sync() {
+        fn assert_send_sync<T: std::error::Error + Send + Sync + 'static>() {}
+        assert_send_sync::<PartialHeaderParseError<io::Error>>();
+        assert_send_sync::<SparseBodyError<io::Error>>();
+        assert_send_sync::<PartParseError>();
+        assert_send_sync::<Unfetched>();
+        assert_send_sync::<crate::streaming::StreamingPartsError>();
+        assert_send_sync::<crate::multipart::MultipartError>();
+        assert_send_sync::<crate::request::RangeParseError>();
+        assert_send_sync::<crate::request::Unsatisfiable>();
+        assert_send_sync::<crate::request::InvalidETag>();
+        assert_send_sync::<crate::request::WeakETag>();
+        assert_send_sync::<crate::remote::RemoteFileError>();
+    }
+
+    #[test]
+    fn widen() {
+        let err = SparseBodyError::from(PartialHeaderParseError::NoContentRange);
+        let err: SparseBodyError<io::Error> = err.widen();
+        assert!(matches!(
+            err,
+            SparseBodyError::Header(PartialHeaderParseError::NoContentRange)
+        ));
+    }
+
+    /// A response without a body which only has a status and `Content-Range`.
+    struct HeadOnly(u16, &'static str);
+
+    impl MaybePartialResponse for HeadOnly {
+        type Error = Infallible;
+
+        fn status_code(&self) -> u16 {
+            self.0
+        }
+
+        fn header_values(&self, name: &str) -> Vec<&str> {
+            if name.eq_ignore_ascii_case(CONTENT_RANGE) {
+                vec![self.1]
+            } else {
+                vec![]
+            }
+        }
+
+        fn body(self) -> Result<Bytes, Infallible> {
+            Ok(Bytes::default())
+        }
+    }
+
+    #[test]
+    fn retry_clamped_ranges() {
+        let mut sent = Vec::default();
+        let resp = retry_clamped(RangeHeader::parse("bytes=0-99,4000-4999").unwrap(), |r| {
+            sent.push(r.to_string());
+            // a server which refuses ranges past the end
+            let status = if sent.len() == 1 { 416 } else { 206 };
+            Ok::<_, ()>(HeadOnly(status, "bytes */4057"))
+        })
+        .unwrap();
+        assert_eq!(resp.status_code(), 206);
+        assert_eq!(sent, ["bytes=0-99,4000-4999", "bytes=0-99,4000-4056"]);
+
+        // nothing to retry
+        for range in ["bytes=5000-", "bytes=0-99"] {
+            let mut count = 0;
+            let resp = retry_clamped(RangeHeader::parse(range).unwrap(), |_| {
+                count += 1;
+                Ok::<_, ()>(HeadOnly(416, "bytes */4057"))
+            })
+            .unwrap();
+            assert_eq!(resp.status_code(), 416);
+            assert_eq!(count, 1);
+        }
+    }
+
+    const FIXTURE_ETAG: &str = "cfbd89a838fb30b7e741d254935240dfc3962e2b386972885cbbbe242ee32eca";
+
+    #[test]
+    fn validators() {
+        test_response("bytes=50-100", |resp| {
+            assert_eq!(resp.etag().unwrap().tag(), FIXTURE_ETAG);
+            assert_eq!(resp.last_modified(), None);
+            assert!(matches!(resp.validator(), Some(Validator::ETag(_))));
+        });
+        test_fixture("multipart", "numeric", |resp| {
+            let expected = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1689802880);
+            assert_eq!(resp.last_modified(), Some(expected));
+            assert_eq!(resp.etag().unwrap().tag(), "64b85870-fd9");
+        });
+    }
+
+    #[test]
+    fn if_range() {
+        let same = IfRange::new(EntityTag::strong(FIXTURE_ETAG).unwrap()).unwrap();
+        let changed = IfRange::new(EntityTag::strong("changed").unwrap()).unwrap();
+        let date = IfRange::new(SystemTime::UNIX_EPOCH).unwrap();
+
+        test_response("bytes=50-100", |resp| {
+            assert!(resp.matches_if_range(&same));
+            assert!(!resp.matches_if_range(&changed));
+            // a 206 is only sent if the If-Range matched
+            assert!(resp.matches_if_range(&date));
+        });
+        test_fixture("handmade", "full", |resp| {
+            assert!(resp.matches_if_range(&same));
+            assert!(!resp.matches_if_range(&date));
+            let bod = resp.sparse_body_if_range(&same).unwrap();
+            assert_eq!(bod.populated(), &[(0, 4057)]);
+        });
+        test_fixture("handmade", "full", |resp| {
+            let result = resp.sparse_body_if_range(&changed);
+            assert!(matches!(
+                result,
+                Err(SparseBodyError::ValidatorMismatch { found: Some(_), .. })
+            ));
+        });
+    }
+
+    #[test]
+    fn merge_changed_representation() {
+        let mut bod = SparseBody::new();
+        test_response("bytes=50-100", |resp| bod.insert_response(resp).unwrap());
+        test_fixture("handmade", "full", |resp| {
+            bod.insert_response(resp).unwrap()
+        });
+        let result = test_fixture("multipart", "numeric", |resp| bod.insert_response(resp));
+        assert!(matches!(
+            result,
+            Err(SparseBodyError::ValidatorMismatch { .. })
+        ));
+        assert_eq!(
+            bod.validator().unwrap().to_string(),
+            format!("\"{FIXTURE_ETAG}\"")
+        );
+        assert_eq!(bod.populated(), &[(0, 4057)]);
+        assert_eq!(bod.sources().len(), 2);
+        for source in bod.sources() {
+            assert_eq!(source.etag.as_ref().unwrap().tag(), FIXTURE_ETAG);
+            assert_eq!(source.complete_length, Some(4057));
+        }
+    }
+
+    #[test]
+    fn merge_conflicting_sources() {
+        let modified = |secs| Some(SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(secs));
+        let source = |etag: Option<&str>, last_modified| Source {
+            etag: etag.map(|t| EntityTag::strong(t).unwrap()),
+            last_modified,
+            complete_length: None,
+        };
+        let mut bod = SparseBody::new();
+        bod.extend_parts_from(source(None, modified(1)), vec![part(0, b"abc", Some(10))])
+            .unwrap();
+
+        let err = bod
+            .extend_parts_from(
+                source(Some("a"), modified(2)),
+                vec![part(3, b"def", Some(10))],
+            )
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            SparseBodyError::ValidatorMismatch {
+                expected: Validator::LastModified(_),
+                ..
+            }
+        ));
+
+        bod.extend_parts_from(
+            source(Some("a"), modified(1)),
+            vec![part(3, b"def", Some(10))],
+        )
+        .unwrap();
+        // compared with every earlier response, not only the first
+        let err = bod
+            .insert_full_from(source(Some("b"), None), Bytes::from_static(b"0123456789"))
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            SparseBodyError::ValidatorMismatch {
+                expected: Validator::ETag(_),
+                ..
+            }
+        ));
+
+        assert_eq!(bod.populated(), &[(0, 6)]);
+        assert_eq!(bod.sources().len(), 2);
+        assert_eq!(bod.sources()[1].complete_length, Some(10));
+        assert_eq!(
+            bod.validator(),
+            Some(Validator::LastModified(modified(1).unwrap()))
+        );
+    }
+
+    #[test]
+    fn overlapping_parts_in_one_call() {
+        let mut bod = SparseBody::new();
+        bod.extend_parts([part(2, b"xyz", Some(10)), part(0, b"abcdef", Some(10))])
+            .unwrap();
+        let mut data = [0; 6];
+        bod.read_exact(&mut data).unwrap();
+        assert_eq!(&data, b"abcdef");
+    }
+
+    #[test]
+    fn rejected_parts_are_not_inserted() {
+        let mut bod = partial([part(0, b"abc", Some(10))]).unwrap();
+        let err = bod
+            .extend_parts([part(3, b"def", Some(10)), part(6, b"ghi", Some(11))])
+            .unwrap_err();
+        assert!(matches!(err, SparseBodyError::LengthMismatch { .. }));
+        assert_eq!(bod.populated(), &[(0, 3)]);
+        assert_eq!(bod.len(), 10);
+    }
+
+    #[test]
+    fn merge_out_of_bounds() {
+        let mut bod = partial([part(0, b"abcdefghij", None)]).unwrap();
+        let err = bod.insert_full(Bytes::from_static(b"abcde")).unwrap_err();
+        assert!(matches!(
+            err,
+            SparseBodyError::OutOfBounds {
+                end: 10,
+                complete_length: 5
+            }
+        ));
+
+        let mut bod = full(Bytes::from_static(b"abcde"));
+        let err = bod.insert_part(part(4, b"ef", None)).unwrap_err();
+        assert!(matches!(err, SparseBodyError::OutOfBounds { end: 6, .. }));
+        assert_eq!(bod.populated(), &[(0, 5)]);
+    }
+
+    #[test]
+    fn missing_from_responses() {
+        let mut bod = SparseBody::new();
+        test_response("bytes=50-100", |resp| bod.insert_response(resp).unwrap());
+        test_response("bytes=3000-", |resp| bod.insert_response(resp).unwrap());
+        let options = MissingRanges::new();
+        assert_eq!(bod.missing(.., &options).to_string(), "bytes=0-49,101-2999");
+        assert_eq!(
+            bod.missing(60..3500, &options).to_string(),
+            "bytes=101-2999"
+        );
+        assert!(bod.missing(3000.., &options).is_empty());
+    }
+
+    /// A file, and spans of it which are valid parts.
+    fn file_and_spans() -> impl Strategy<Value = (Vec<u8>, Vec<(usize, usize)>)> {
+        prop::collection::vec(any::<u8>(), 1..2000).prop_flat_map(|file| {
+            let len = file.len();
+            let span = (0..len).prop_flat_map(move |offset| (Just(offset), 1..=len - offset));
+            (Just(file), prop::collection::vec(span, 1..10))
+        })
+    }
+
+    /// Bodies made of fragments which are likely to confuse a multipart parser.
+    fn multipart_like() -> impl Strategy<Value = Vec<u8>> {
+        let fragment = prop_oneof![
+            Just(b"--b".to_vec()),
+            Just(b"--".to_vec()),
+            Just(b"\r\n".to_vec()),
+            Just(b"\n".to_vec()),
+            Just(b"Content-Type: text/plain".to_vec()),
+            "Content-Range: bytes [0-9]{1,3}-[0-9]{1,3}/[0-9*]{1,3}".prop_map(String::into_bytes),
+            "[a-zA-Z: -]{0,8}".prop_map(String::into_bytes),
+            prop::collection::vec(any::<u8>(), 0..8),
+        ];
+        prop::collection::vec(fragment, 0..40).prop_map(|v| v.concat())
+    }
+
+    proptest! {
+        #[test]
+        fn multipart_roundtrip((file, spans) in file_and_spans()) {
+            let file = Bytes::from(file);
+            let body = MultipartBuilder::new("application/octet-stream")
+                .complete_length(file.len())
+                .extend(spans.iter().map(|&(o, l)| (o, file.slice(o..o + l))))
+                .build()
+                .unwrap();
+            let part_desc = || part_description(206, Some(&body.content_type()), None).unwrap();
+            let parts: Vec<_> = Parts::new(part_desc(), body.to_bytes())
+                .collect::<Result<_, _>>()
+                .unwrap();
+            let lenient: Vec<_> = Parts::new(part_desc(), body.to_bytes())
+                .set_lenient(true)
+                .collect::<Result<_, _>>()
+                .unwrap();
+            prop_assert_eq!(
+                lenient.iter().map(|p| p.data()).collect::<Vec<_>>(),
+                parts.iter().map(|p| p.data()).collect::<Vec<_>>()
+            );
+            prop_assert_eq!(parts.len(), spans.len());
+            for (part, &(offset, len)) in parts.iter().zip(spans.iter()) {
+                prop_assert_eq!(part.offset_len(), Some((offset, len)));
+                prop_assert_eq!(part.data(), &file[offset..offset + len]);
+            }
+
+            let mut bod = partial(parts).unwrap();
+            for &(offset, len) in spans.iter() {
+                prop_assert!(bod.contains(offset..offset + len));
+                let mut held = bod.get_buf(offset..offset + len).unwrap();
+                prop_assert_eq!(held.copy_to_bytes(len), &file[offset..offset + len]);
+            }
+            let mut buf = Vec::default();
+            bod.read_to_end(&mut buf).unwrap();
+            prop_assert_eq!(buf.len(), file.len());
+            for (idx, (actual, expected)) in buf.iter().zip(file.iter()).enumerate() {
+                let held = spans.iter().any(|&(o, l)| (o..o + l).contains(&idx));
+                prop_assert_eq!(*actual, if held { *expected } else { 0 }, "byte {}", idx);
+            }
+        }
+
+        #[test]
+        fn arbitrary_body_does_not_panic(body in multipart_like()) {
+            let part_desc = PartDesc::Multi { boundary: b"--b".to_vec() };
+            let parts: Result<Vec<_>, _> = Parts::new(part_desc, body.into()).collect();
+            if let Ok(parts) = parts {
+                let _ = partial(parts);
+            }
+        }
+
+        #[test]
+        fn arbitrary_headers_do_not_panic(
+            status in prop_oneof![Just(200u16), Just(206), Just(416), any::<u16>()],
+            content_type in proptest::option::of(
+                "(multipart/byteranges|text/plain)?[ ;=\"\\a-zA-Z0-9]{0,30}"
+            ),
+            content_range in proptest::option::of("(bytes )?[0-9*/ -]{0,20}"),
+        ) {
+            let _ = part_description(status, content_type.as_deref(), content_range.as_deref());
+        }
+    }
 }
@@ -0,0 +1,630 @@
+use std::io::{self, Read};
+
+use bytes::{Buf, BufMut, BytesMut};
+use http_content_range::ContentRange;
+use thiserror::Error;
+
+use crate::response::{
+    check_boundary, check_part_len, find, find_header, offset_len, parse_part_headers, Bytes,
+    PartDesc, PartHead, PartParseError, PartParseErrorKind, ResponsePart,
+};
+
+#[cfg(feature = "http-body")]
+pub use crate::impls::http_body_impl::{BodyParts, BodyPartsError};
+
+const CHUNK_SIZE: usize = 8 * 1024;
+/// Part headers longer than this are treated as malformed rather than buffered indefinitely.
+const MAX_HEADER_LEN: usize = 64 * 1024;
+
+#[derive(Debug, Error)]
+pub enum StreamingPartsError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Part(#[from] PartParseError),
+}
+
+impl From<StreamingPartsError> for io::Error {
+    fn from(value: StreamingPartsError) -> Self {
+        match value {
+            StreamingPartsError::Io(e) => e,
+            StreamingPartsError::Part(e) => io::Error::new(io::ErrorKind::InvalidData, e),
+        }
+    }
+}
+
+/// What a [PartsDecoder] found next in the body.
+pub(crate) enum Event {
+    /// More of the body must be fed, or the decoder told it has finished.
+    NeedData,
+    /// The headers of the next part, whose data follows.
+    Part(PartHead),
+    /// Some of the current part's data.
+    Data(Bytes),
+    /// The end of the current part's data, whose length has been checked.
+    PartEnd,
+    /// There are no more parts.
+    Done,
+}
+
+enum State {
+    /// Before the first boundary.
+    Start,
+    /// After a boundary, before the `CRLF` or `--` which follows it.
+    Boundary,
+    /// Before the part headers.
+    Headers,
+    /// Inside a part's data, which started at the given position in the body.
+    Data(usize, ContentRange),
+    Done,
+}
+
+/// Parser for the parts of a 206 Partial response body which does no IO of its own,
+/// driven by [StreamingParts] and [BodyParts] as the body arrives.
+///
+/// The body is [fed](PartsDecoder::feed) to it in pieces of any size,
+/// and each call to [PartsDecoder::next_event] returns what can be parsed from them so far.
+pub(crate) struct PartsDecoder {
+    part_desc: PartDesc,
+    /// Bytes fed but not yet consumed.
+    buf: BytesMut,
+    /// `CRLF--boundary`, which ends each part's data.
+    delimiter: Vec<u8>,
+    state: State,
+    /// Position in the body of the start of `buf`.
+    position: usize,
+    /// Whether the whole body has been fed.
+    finished: bool,
+}
+
+impl PartsDecoder {
+    pub fn new(part_desc: PartDesc) -> Self {
+        let delimiter = match &part_desc {
+            PartDesc::Single { .. } => Vec::default(),
+            PartDesc::Multi { boundary } => [b"\r\n", boundary.as_slice()].concat(),
+        };
+        Self {
+            part_desc,
+            buf: BytesMut::default(),
+            delimiter,
+            state: State::Start,
+            position: 0,
+            finished: false,
+        }
+    }
+
+    /// Add the next piece of the body.
+    pub fn feed<D: Buf>(&mut self, data: D) {
+        self.buf.put(data);
+    }
+
+    /// Note that the whole body has been fed.
+    pub fn finish(&mut self) {
+        self.finished = true;
+    }
+
+    /// Stop parsing, as after failing to receive the body.
+    #[cfg(feature = "http-body")]
+    pub fn abort(&mut self) {
+        self.state = State::Done;
+    }
+
+    /// Whether the current part's data is still being parsed.
+    pub fn in_data(&self) -> bool {
+        matches!(self.state, State::Data(..))
+    }
+
+    /// Parse as far as the next event.
+    ///
+    /// Once an error has been returned, there are no more parts.
+    pub fn next_event(&mut self) -> Result<Event, PartParseError> {
+        let result = self.parse();
+        if result.is_err() {
+            self.state = State::Done;
+        }
+        result
+    }
+
+    fn parse(&mut self) -> Result<Event, PartParseError> {
+        loop {
+            match self.state {
+                State::Start => {
+                    let (found, boundary_len) = match &self.part_desc {
+                        PartDesc::Single {
+                            content_range,
+                            content_type,
+                        } => {
+                            self.state = State::Data(0, *content_range);
+                            return Ok(Event::Part(PartHead {
+                                len: 0,
+                                content_type: content_type.clone(),
+                                content_range: *content_range,
+                                headers: Vec::default(),
+                            }));
+                        }
+                        PartDesc::Multi { boundary } => {
+                            check_boundary(boundary)?;
+                            (find(&self.buf, boundary), boundary.len())
+                        }
+                    };
+                    if let Some(idx) = found {
+                        self.consume(idx + boundary_len);
+                        self.state = State::Boundary;
+                        continue;
+                    }
+                    // keep enough to match a boundary split across pieces
+                    let keep = boundary_len - 1;
+                    if self.buf.len() > keep {
+                        self.consume(self.buf.len() - keep);
+                    }
+                    if self.finished {
+                        return Err(PartParseError::new(0, PartParseErrorKind::NoBoundary));
+                    }
+                    return Ok(Event::NeedData);
+                }
+                State::Boundary => {
+                    if self.buf.len() < 2 {
+                        return self.need_data(PartParseErrorKind::MissingCrlf);
+                    }
+                    self.state = match &self.buf[..2] {
+                        b"\r\n" => State::Headers,
+                        b"--" => State::Done,
+                        _ => return Err(self.error(PartParseErrorKind::MissingCrlf)),
+                    };
+                    self.consume(2);
+                }
+                State::Headers => {
+                    if let Some(head) =
+                        parse_part_headers(&self.buf, self.position, Bytes::copy_from_slice)?
+                    {
+                        self.consume(head.len);
+                        self.state = State::Data(self.position, head.content_range);
+                        return Ok(Event::Part(head));
+                    }
+                    if self.buf.len() > MAX_HEADER_LEN {
+                        return Err(self.error(PartParseErrorKind::HeadersTooLong));
+                    }
+                    return self.need_data(PartParseErrorKind::TruncatedHeaders);
+                }
+                State::Data(start, content_range) => return self.data(start, content_range),
+                State::Done => return Ok(Event::Done),
+            }
+        }
+    }
+
+    /// Parse the current part's data, which started at `start`.
+    fn data(&mut self, start: usize, content_range: ContentRange) -> Result<Event, PartParseError> {
+        // a single part runs to the end of the body
+        if self.delimiter.is_empty() {
+            if !self.buf.is_empty() {
+                self.check_not_longer(start, &content_range, self.buf.len())?;
+                return Ok(Event::Data(self.consume(self.buf.len())));
+            }
+            if !self.finished {
+                return Ok(Event::NeedData);
+            }
+            check_part_len(&content_range, self.position - start, start)?;
+            self.state = State::Done;
+            return Ok(Event::PartEnd);
+        }
+        let delim_len = self.delimiter.len();
+        let found = find(&self.buf, &self.delimiter);
+        if found == Some(0) {
+            check_part_len(&content_range, self.position - start, start)?;
+            self.consume(delim_len);
+            self.state = State::Boundary;
+            return Ok(Event::PartEnd);
+        }
+        // without a delimiter, the end of the buffer may be the start of one
+        let available = found.unwrap_or(self.buf.len().saturating_sub(delim_len - 1));
+        if available > 0 {
+            self.check_not_longer(start, &content_range, available)?;
+            return Ok(Event::Data(self.consume(available)));
+        }
+        if self.finished {
+            return Err(PartParseError::new(
+                start,
+                PartParseErrorKind::MissingDelimiter,
+            ));
+        }
+        Ok(Event::NeedData)
+    }
+
+    /// Fail if `n` more bytes of data would make the current part, which started at `start`,
+    /// longer than its `Content-Range` allows, rather than waiting for the end of its data.
+    fn check_not_longer(
+        &self,
+        start: usize,
+        content_range: &ContentRange,
+        n: usize,
+    ) -> Result<(), PartParseError> {
+        let found = self.position - start + n;
+        match offset_len(content_range) {
+            Some((_, expected)) if found > expected => Err(PartParseError::new(
+                start,
+                PartParseErrorKind::LengthMismatch { expected, found },
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Ask for more of the body, or fail with `kind` if there is no more.
+    fn need_data(&self, kind: PartParseErrorKind) -> Result<Event, PartParseError> {
+        if self.finished {
+            Err(self.error(kind))
+        } else {
+            Ok(Event::NeedData)
+        }
+    }
+
+    /// Remove `n` bytes from the start of the buffer, returning them.
+    fn consume(&mut self, n: usize) -> Bytes {
+        self.position += n;
+        self.buf.split_to(n).freeze()
+    }
+
+    fn error(&self, kind: PartParseErrorKind) -> PartParseError {
+        PartParseError::new(self.position, kind)
+    }
+}
+
+/// Streaming counterpart to [Parts](crate::response::Parts), over the [Read]able body of a 206 Partial response.
+///
+/// Rather than loading the whole body into memory,
+/// each call to [StreamingParts::next_part] parses the next part's headers
+/// and returns a [StreamingPart] which reads only that part's data.
+/// Only a small buffer is held at any time,
+/// and a part's data is cut off with an error as soon as it runs past the length in its `Content-Range`.
+///
+/// ```rust
+/// # use byteranges::streaming::StreamingParts;
+/// # use byteranges::response::PartDesc;
+/// # use std::io::Read;
+/// let body = b"--sep\r\nContent-Type: text/plain\r\nContent-Range: bytes 5-9/20\r\n\r\nhello\r\n--sep--\r\n";
+/// let part_desc = PartDesc::Multi { boundary: b"--sep".to_vec() };
+/// let mut parts = StreamingParts::new(part_desc, &body[..]);
+/// while let Some(mut part) = parts.next_part().unwrap() {
+///     assert_eq!(part.offset_len(), Some((5, 5)));
+///     let mut data = Vec::default();
+///     part.read_to_end(&mut data).unwrap();
+///     assert_eq!(data, b"hello");
+/// }
+/// ```
+pub struct StreamingParts<R: Read> {
+    reader: R,
+    decoder: PartsDecoder,
+    /// Data of the current part which has been parsed but not yet read.
+    pending: Bytes,
+}
+
+impl<R: Read> StreamingParts<R> {
+    pub fn new(part_desc: PartDesc, reader: R) -> Self {
+        Self {
+            reader,
+            decoder: PartsDecoder::new(part_desc),
+            pending: Bytes::new(),
+        }
+    }
+
+    /// Feed another chunk from the reader to the decoder.
+    fn fill(&mut self) -> io::Result<()> {
+        let mut chunk = [0; CHUNK_SIZE];
+        match self.reader.read(&mut chunk)? {
+            0 => self.decoder.finish(),
+            n => self.decoder.feed(&chunk[..n]),
+        }
+        Ok(())
+    }
+
+    /// Parse the headers of the next part.
+    ///
+    /// Any unread data from the previous part is skipped.
+    /// Returns [None] once the closing boundary has been reached.
+    pub fn next_part(&mut self) -> Result<Option<StreamingPart<'_, R>>, StreamingPartsError> {
+        self.pending.clear();
+        let head = loop {
+            match self.decoder.next_event()? {
+                Event::NeedData => self.fill()?,
+                Event::Part(head) => break head,
+                Event::Data(_) | Event::PartEnd => (),
+                Event::Done => return Ok(None),
+            }
+        };
+        Ok(Some(StreamingPart {
+            parts: self,
+            content_type: head.content_type,
+            content_range: head.content_range,
+            headers: head.headers,
+        }))
+    }
+
+    /// Read data from the current part.
+    fn read_data(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        while self.pending.is_empty() {
+            if !self.decoder.in_data() {
+                return Ok(0);
+            }
+            match self
+                .decoder
+                .next_event()
+                .map_err(StreamingPartsError::from)?
+            {
+                Event::NeedData => self.fill()?,
+                Event::Data(data) => self.pending = data,
+                _ => (),
+            }
+        }
+        let n = buf.len().min(self.pending.len());
+        buf[..n].copy_from_slice(&self.pending[..n]);
+        self.pending.advance(n);
+        Ok(n)
+    }
+}
+
+/// A single part of a response being streamed by [StreamingParts].
+///
+/// The part headers are available immediately,
+/// and the part's data can be [Read] without buffering it all.
+pub struct StreamingPart<'a, R: Read> {
+    parts: &'a mut StreamingParts<R>,
+    content_type: String,
+    content_range: ContentRange,
+    headers: Vec<(String, Bytes)>,
+}
+
+impl<R: Read> StreamingPart<'_, R> {
+    pub fn content_type(&self) -> &str {
+        &self.content_type
+    }
+
+    /// All of the part's headers; see [ResponsePart::headers].
+    pub fn headers(&self) -> &[(String, Bytes)] {
+        &self.headers
+    }
+
+    /// The value of the first of the part's headers with the given name, case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&[u8]> {
+        find_header(&self.headers, name)
+    }
+
+    /// The offset and length of the part according to the `Content-Range` header.
+    ///
+    /// If the range was unsatisfied or the content range was not parseable,
+    /// return [None].
+    pub fn offset_len(&self) -> Option<(usize, usize)> {
+        offset_len(&self.content_range)
+    }
+
+    /// The size according to the `Content-Range` header.
+    ///
+    /// [None] if the header did not express that information.
+    pub fn total_size(&self) -> Option<usize> {
+        match &self.content_range {
+            ContentRange::Bytes(r) => Some(r.complete_length as usize),
+            _ => None,
+        }
+    }
+
+    /// Read the rest of the part's data into memory.
+    pub fn into_response_part(mut self) -> io::Result<ResponsePart> {
+        let mut data = Vec::default();
+        self.read_to_end(&mut data)?;
+        Ok(
+            ResponsePart::new(self.content_type, self.content_range, data.into())
+                .with_headers(self.headers),
+        )
+    }
+}
+
+impl<R: Read> Read for StreamingPart<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.parts.read_data(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::multipart::MultipartBuilder;
+    use crate::response::{Bytes, MaybePartialResponse, Parts};
+    use crate::test_impl::{malformed_bodies, read_text, test_response};
+
+    /// Reader which returns at most `chunk` bytes per read.
+    struct Chunked<'a> {
+        data: &'a [u8],
+        chunk: usize,
+    }
+
+    impl Read for Chunked<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = self.chunk.min(buf.len()).min(self.data.len());
+            buf[..n].copy_from_slice(&self.data[..n]);
+            self.data = &self.data[n..];
+            Ok(n)
+        }
+    }
+
+    fn multipart(spans: &[(usize, usize)]) -> (String, Bytes) {
+        let text = Bytes::from(read_text());
+        let body = MultipartBuilder::new("text/plain")
+            .complete_length(text.len())
+            .extend(spans.iter().map(|&(o, l)| (o, text.slice(o..o + l))))
+            .build()
+            .unwrap();
+        (format!("--{}", body.boundary()), body.to_bytes())
+    }
+
+    #[test]
+    fn matches_parts_for_any_chunk_size() {
+        let (boundary, body) = multipart(&[(0, 1), (10, 200), (3000, 1057)]);
+        let part_desc = || PartDesc::Multi {
+            boundary: boundary.as_bytes().to_vec(),
+        };
+        let expected: Vec<_> = Parts::new(part_desc(), body.clone())
+            .collect::<Result<_, _>>()
+            .unwrap();
+        for chunk in [1, 2, 3, 7, 64, 1000, body.len()] {
+            let mut parts = StreamingParts::new(part_desc(), Chunked { data: &body, chunk });
+            let mut count = 0;
+            while let Some(part) = parts.next_part().unwrap() {
+                let part = part.into_response_part().unwrap();
+                let exp = &expected[count];
+                assert_eq!(part.offset_len(), exp.offset_len(), "chunk {chunk}");
+                assert_eq!(part.data(), exp.data(), "chunk {chunk}");
+                count += 1;
+            }
+            assert_eq!(count, expected.len());
+        }
+    }
+
+    #[test]
+    fn part_headers() {
+        let (boundary, body) = multipart(&[(0, 100), (200, 100)]);
+        let part_desc = PartDesc::Multi {
+            boundary: boundary.into_bytes(),
+        };
+        let mut parts = StreamingParts::new(
+            part_desc,
+            Chunked {
+                data: &body,
+                chunk: 7,
+            },
+        );
+        let part = parts.next_part().unwrap().unwrap();
+        assert_eq!(part.header("content-type"), Some(&b"text/plain"[..]));
+        let part = part.into_response_part().unwrap();
+        let names: Vec<_> = part.headers().iter().map(|(n, _)| n.as_str()).collect();
+        assert_eq!(names, ["Content-Type", "Content-Range"]);
+        assert_eq!(part.header("Content-Range"), Some(&b"bytes 0-99/4057"[..]));
+    }
+
+    #[test]
+    fn skips_unread_data() {
+        let (boundary, body) = multipart(&[(0, 100), (200, 100)]);
+        let part_desc = PartDesc::Multi {
+            boundary: boundary.into_bytes(),
+        };
+        let mut parts = StreamingParts::new(
+            part_desc,
+            Chunked {
+                data: &body,
+                chunk: 5,
+            },
+        );
+        let mut first = parts.next_part().unwrap().unwrap();
+        let mut buf = [0; 10];
+        first.read_exact(&mut buf).unwrap();
+        let second = parts.next_part().unwrap().unwrap();
+        assert_eq!(second.offset_len(), Some((200, 100)));
+        assert_eq!(second.into_response_part().unwrap().data().len(), 100);
+        assert!(parts.next_part().unwrap().is_none());
+    }
+
+    #[test]
+    fn truncated_body() {
+        let (boundary, body) = multipart(&[(0, 100)]);
+        let part_desc = PartDesc::Multi {
+            boundary: boundary.into_bytes(),
+        };
+        let truncated = &body[..body.len() - 20];
+        let mut parts = StreamingParts::new(part_desc, truncated);
+        let part = parts.next_part().unwrap().unwrap();
+        assert!(part.into_response_part().is_err());
+    }
+
+    #[test]
+    fn bounded_by_content_range() {
+        let head = "--B\r\nContent-Type: text/plain\r\nContent-Range: bytes 0-2/10\r\n\r\n";
+        let mut body = head.as_bytes().to_vec();
+        body.extend(std::iter::repeat_n(b'x', 100_000));
+        body.extend_from_slice(b"\r\n--B--");
+        let multi = PartDesc::Multi {
+            boundary: b"--B".to_vec(),
+        };
+        let single = PartDesc::Single {
+            content_type: "text/plain".to_owned(),
+            content_range: ContentRange::parse("bytes 0-2/10"),
+        };
+        for (part_desc, data) in [(multi, &body[..]), (single, &body[head.len()..])] {
+            let mut parts = StreamingParts::new(part_desc, Chunked { data, chunk: 7 });
+            let mut part = parts.next_part().unwrap().unwrap();
+            let mut read = 0;
+            let mut buf = [0; 100];
+            let err = loop {
+                match part.read(&mut buf) {
+                    Ok(0) => panic!("no error"),
+                    Ok(n) => read += n,
+                    Err(e) => break e,
+                }
+            };
+            assert!(read <= 3);
+            let err: Box<PartParseError> = err.into_inner().unwrap().downcast().unwrap();
+            assert!(matches!(
+                err.kind,
+                PartParseErrorKind::LengthMismatch { expected: 3, found } if found < 20
+            ));
+        }
+    }
+
+    #[test]
+    fn single_part_response() {
+        let reference = read_text();
+        test_response("bytes=50-100", |resp| {
+            let mut parts = resp.streaming_parts().unwrap();
+            let part = parts.next_part().unwrap().unwrap();
+            assert_eq!(part.offset_len(), Some((50, 51)));
+            let part = part.into_response_part().unwrap();
+            assert_eq!(part.data(), &reference[50..=100]);
+            assert!(parts.next_part().unwrap().is_none());
+        });
+    }
+
+    #[test]
+    fn invalid_boundaries() {
+        for boundary in [&b""[..], b"--", b"B", b"--B\r\n", b"--\xff"] {
+            let part_desc = PartDesc::Multi {
+                boundary: boundary.to_vec(),
+            };
+            let mut parts = StreamingParts::new(part_desc, &b"--B\r\n"[..]);
+            match parts.next_part() {
+                Err(StreamingPartsError::Part(e)) => {
+                    assert_eq!(e.kind, PartParseErrorKind::InvalidBoundary)
+                }
+                _ => panic!("no error for {boundary:?}"),
+            }
+            assert!(parts.next_part().unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn malformed_parts() {
+        for (body, expected) in malformed_bodies() {
+            let part_desc = PartDesc::Multi {
+                boundary: b"--B".to_vec(),
+            };
+            let mut parts = StreamingParts::new(
+                part_desc,
+                Chunked {
+                    data: &body,
+                    chunk: 3,
+                },
+            );
+            let err = loop {
+                match parts.next_part() {
+                    Ok(Some(part)) => {
+                        if let Err(e) = part.into_response_part() {
+                            break e.into_inner().unwrap().downcast().unwrap();
+                        }
+                    }
+                    Ok(None) => panic!("no error for {body:?}"),
+                    Err(StreamingPartsError::Part(e)) => break Box::new(e),
+                    Err(e) => panic!("{e}"),
+                }
+            };
+            assert_eq!(*err, expected);
+        }
+    }
+}
@@ -0,0 +1,532 @@
+use std::{
+    fs,
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+    time::{Duration, SystemTime},
+};
+
+use httparse::{Request, Status, EMPTY_HEADER};
+
+use crate::request::{EntityTag, IfRange, RangeHeader, IF_RANGE, RANGE};
+use crate::response::{find_header, Bytes, RangeResponse, Representation};
+
+/// Requests with longer headers are refused.
+const MAX_REQUEST_LEN: usize = 64 * 1024;
+const MAX_HEADERS: usize = 64;
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Ways in which a [TestServer] can be made to break the rules, to test how clients cope.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Misbehaviour {
+    /// Respond to every request with the whole representation, while still claiming to accept ranges.
+    IgnoreRange,
+    /// Add a part spanning all of the requested ranges to every partial response, overlapping the others.
+    OverlappingParts,
+    /// Report a `Content-Length` one byte shorter than the body.
+    WrongContentLength,
+}
+
+/// A request received by a [TestServer].
+#[derive(Debug, Clone)]
+pub struct ReceivedRequest {
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, Bytes)>,
+}
+
+impl ReceivedRequest {
+    /// The value of the first header with the given name, case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&[u8]> {
+        find_header(&self.headers, name)
+    }
+
+    fn header_str(&self, name: &str) -> Option<&str> {
+        self.header(name).and_then(|v| std::str::from_utf8(v).ok())
+    }
+}
+
+/// HTTP/1.1 server on a local port which serves a single representation at every path,
+/// for tests and local development.
+///
+/// `GET` requests are answered as by a [Representation]: with single-part and `multipart/byteranges` 206 responses,
+/// 416 responses for unsatisfiable ranges, and the whole representation if the `If-Range` does not match.
+/// `HEAD` requests get the headers of the whole representation.
+/// Range support can be switched off (see [TestServer::set_accept_ranges]),
+/// and the server can be made to [TestServer::misbehave].
+///
+/// ```rust
+/// # use byteranges::test_server::TestServer;
+/// # use byteranges::response::Bytes;
+/// let server = TestServer::new(Bytes::from_static(b"0123456789")).serve().unwrap();
+/// let url = server.url();
+/// // the server stops when the handle is dropped
+/// ```
+#[derive(Debug)]
+pub struct TestServer {
+    representation: Representation,
+    accept_ranges: bool,
+    misbehaviours: Vec<Misbehaviour>,
+}
+
+impl TestServer {
+    pub fn new(data: Bytes) -> Self {
+        Representation::new(data).into()
+    }
+
+    /// Serve the contents of a file, with its modification date as `Last-Modified`
+    /// and an `ETag` made from that and its length.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let data = Bytes::from(fs::read(&path)?);
+        let modified = fs::metadata(&path)?.modified()?;
+        let secs = modified
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let etag = EntityTag::strong(format!("{secs:x}-{:x}", data.len()))
+            .expect("hex digits are valid etagc");
+        let mut representation = Representation::new(data);
+        representation
+            .set_etag(Some(etag))
+            .set_last_modified(Some(modified));
+        Ok(representation.into())
+    }
+
+    /// The representation being served.
+    pub fn representation(&self) -> &Representation {
+        &self.representation
+    }
+
+    /// Change the representation before the server is started.
+    pub fn representation_mut(&mut self) -> &mut Representation {
+        &mut self.representation
+    }
+
+    /// Whether to support range requests (default true).
+    ///
+    /// If not, every `GET` gets the whole representation, with `Accept-Ranges: none`.
+    pub fn set_accept_ranges(&mut self, accept_ranges: bool) -> &mut Self {
+        self.accept_ranges = accept_ranges;
+        self
+    }
+
+    /// Break the rules in the given way, as well as any others already set.
+    pub fn misbehave(&mut self, misbehaviour: Misbehaviour) -> &mut Self {
+        self.misbehaviours.push(misbehaviour);
+        self
+    }
+
+    fn misbehaves(&self, misbehaviour: Misbehaviour) -> bool {
+        self.misbehaviours.contains(&misbehaviour)
+    }
+
+    /// Start serving on a free port on localhost, in a background thread.
+    pub fn serve(self) -> io::Result<ServerHandle> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        let requests = Arc::new(Mutex::<Vec<ReceivedRequest>>::default());
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let thread_requests = Arc::clone(&requests);
+        let thread_stop = Arc::clone(&stop);
+        let thread = std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                if thread_stop.load(Ordering::SeqCst) {
+                    break;
+                }
+                if let Ok(stream) = stream {
+                    // a client which goes away only affects its own request
+                    let _ = self.handle(stream, &thread_requests);
+                }
+            }
+        });
+        Ok(ServerHandle {
+            addr,
+            requests,
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    fn handle(
+        &self,
+        mut stream: TcpStream,
+        requests: &Mutex<Vec<ReceivedRequest>>,
+    ) -> io::Result<()> {
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+        let mut buf = Vec::default();
+        let mut chunk = [0; 4096];
+        let request = loop {
+            let n = stream.read(&mut chunk)?;
+            if n == 0 {
+                return Ok(());
+            }
+            buf.extend_from_slice(&chunk[..n]);
+            let mut headers = [EMPTY_HEADER; MAX_HEADERS];
+            let mut request = Request::new(&mut headers);
+            match request.parse(&buf) {
+                Ok(Status::Complete(_)) => {
+                    break ReceivedRequest {
+                        method: request.method.unwrap_or_default().to_owned(),
+                        path: request.path.unwrap_or_default().to_owned(),
+                        headers: request
+                            .headers
+                            .iter()
+                            .map(|h| (h.name.to_owned(), Bytes::copy_from_slice(h.value)))
+                            .collect(),
+                    }
+                }
+                Ok(Status::Partial) if buf.len() < MAX_REQUEST_LEN => continue,
+                _ => {
+                    return write_response(&mut stream, &status_only(400), true);
+                }
+            }
+        };
+        let response = self.respond(&request);
+        requests
+            .lock()
+            .expect("lock poisoned")
+            .push(request.clone());
+        write_response(&mut stream, &response, request.method != "HEAD")
+    }
+
+    fn respond(&self, request: &ReceivedRequest) -> RangeResponse {
+        if request.method != "GET" && request.method != "HEAD" {
+            return status_only(405);
+        }
+        // an invalid Range is ignored, as is a Range which this server cannot apply
+        let mut range = request
+            .header_str(RANGE)
+            .and_then(|v| RangeHeader::parse(v).ok())
+            .unwrap_or_default();
+        // an If-Range which cannot match, e.g. with a weak entity tag, means the whole representation
+        let if_range = match request.header_str(IF_RANGE) {
+            Some(v) => match IfRange::parse(v) {
+                Some(ir) => Some(ir),
+                None => {
+                    range = RangeHeader::default();
+                    None
+                }
+            },
+            None => None,
+        };
+        if request.method == "HEAD"
+            || !self.accept_ranges
+            || self.misbehaves(Misbehaviour::IgnoreRange)
+        {
+            range = RangeHeader::default();
+        }
+        if self.misbehaves(Misbehaviour::OverlappingParts) {
+            if let Ok(spans) = range.resolve(self.representation.data().len()) {
+                let start = spans.iter().map(|(o, _)| *o).min().unwrap_or(0);
+                let end = spans.iter().map(|(o, l)| o + l).max().unwrap_or(0);
+                range.push(start as u64..end as u64);
+            }
+        }
+
+        let mut response = match self.representation.respond(&range, if_range.as_ref()) {
+            Ok(r) => r,
+            Err(_) => return status_only(500),
+        };
+        for (name, value) in response.headers.iter_mut() {
+            if name.eq_ignore_ascii_case("Accept-Ranges") && !self.accept_ranges {
+                *value = Bytes::from_static(b"none");
+            }
+            if name.eq_ignore_ascii_case("Content-Length")
+                && self.misbehaves(Misbehaviour::WrongContentLength)
+            {
+                *value = Bytes::from(response.body.len().saturating_sub(1).to_string());
+            }
+        }
+        response
+    }
+}
+
+impl From<Representation> for TestServer {
+    fn from(representation: Representation) -> Self {
+        Self {
+            representation,
+            accept_ranges: true,
+            misbehaviours: Vec::default(),
+        }
+    }
+}
+
+fn status_only(status: u16) -> RangeResponse {
+    RangeResponse {
+        status,
+        headers: vec![("Content-Length".to_owned(), Bytes::from_static(b"0"))],
+        body: Bytes::default(),
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        206 => "Partial Content",
+        400 => "Bad Request",
+        405 => "Method Not Allowed",
+        416 => "Range Not Satisfiable",
+        _ => "Internal Server Error",
+    }
+}
+
+fn write_response<W: Write>(mut writer: W, response: &RangeResponse, body: bool) -> io::Result<()> {
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\n",
+        response.status,
+        reason(response.status)
+    )
+    .into_bytes();
+    for (name, value) in response.headers.iter() {
+        head.extend_from_slice(name.as_bytes());
+        head.extend_from_slice(b": ");
+        head.extend_from_slice(value);
+        head.extend_from_slice(b"\r\n");
+    }
+    head.extend_from_slice(b"Connection: close\r\n\r\n");
+    writer.write_all(&head)?;
+    if body {
+        writer.write_all(&response.body)?;
+    }
+    writer.flush()
+}
+
+/// Handle to a running [TestServer], which stops it when dropped.
+#[derive(Debug)]
+pub struct ServerHandle {
+    addr: SocketAddr,
+    requests: Arc<Mutex<Vec<ReceivedRequest>>>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl ServerHandle {
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// URL of the representation (any other path would do as well).
+    pub fn url(&self) -> String {
+        format!("http://{}/", self.addr)
+    }
+
+    /// The requests received so far, in order.
+    pub fn requests(&self) -> Vec<ReceivedRequest> {
+        self.requests.lock().expect("lock poisoned").clone()
+    }
+}
+
+impl Drop for ServerHandle {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        // wake the listener so that it sees the flag
+        let _ = TcpStream::connect(self.addr);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::response::{MaybePartialResponse, PartialHeaderParseError};
+    use crate::test_impl::{lorem, read_text};
+    use httparse::Response;
+
+    fn test_server() -> TestServer {
+        lorem().into()
+    }
+
+    /// Send a request and read the response, trusting its `Content-Length` as a client would.
+    fn send(server: &ServerHandle, method: &str, headers: &[(&str, &str)]) -> RangeResponse {
+        let mut stream = TcpStream::connect(server.addr()).unwrap();
+        let mut request = format!("{method} / HTTP/1.1\r\nHost: {}\r\n", server.addr());
+        for (name, value) in headers {
+            request.push_str(&format!("{name}: {value}\r\n"));
+        }
+        request.push_str("\r\n");
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut buf = Vec::default();
+        stream.read_to_end(&mut buf).unwrap();
+
+        let mut hbuf = [EMPTY_HEADER; MAX_HEADERS];
+        let mut response = Response::new(&mut hbuf);
+        let Status::Complete(offset) = response.parse(&buf).unwrap() else {
+            panic!("incomplete response");
+        };
+        let headers: Vec<_> = response
+            .headers
+            .iter()
+            .map(|h| (h.name.to_owned(), Bytes::copy_from_slice(h.value)))
+            .collect();
+        let content_length: usize =
+            std::str::from_utf8(find_header(&headers, "Content-Length").unwrap())
+                .unwrap()
+                .parse()
+                .unwrap();
+        let body = &buf[offset..];
+        RangeResponse {
+            status: response.code.unwrap(),
+            body: Bytes::copy_from_slice(&body[..content_length.min(body.len())]),
+            headers,
+        }
+    }
+
+    #[test]
+    fn ranges() {
+        let text = read_text();
+        let server = test_server().serve().unwrap();
+
+        let resp = send(&server, "GET", &[("Range", "bytes=50-100")]);
+        assert_eq!(resp.status(), 206);
+        assert_eq!(resp.header("Accept-Ranges"), Some(&b"bytes"[..]));
+        let part = resp.parts().unwrap().next().unwrap().unwrap();
+        assert_eq!(part.offset_len(), Some((50, 51)));
+        assert_eq!(part.data(), &text[50..101]);
+
+        let resp = send(&server, "GET", &[("Range", "bytes=0-9, -10")]);
+        let bod = resp.sparse_body().unwrap();
+        assert_eq!(bod.populated(), &[(0, 10), (4047, 10)]);
+
+        let resp = send(&server, "GET", &[("Range", "bytes=5000-")]);
+        assert!(matches!(
+            resp.part_description(),
+            Err(PartialHeaderParseError::Unsatisfied {
+                complete_length: Some(4057)
+            })
+        ));
+
+        let resp = send(&server, "HEAD", &[("Range", "bytes=50-100")]);
+        assert_eq!(resp.status(), 200);
+        assert_eq!(resp.header("Content-Length"), Some(&b"4057"[..]));
+        assert!(resp.data().is_empty());
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 4);
+        assert_eq!(requests[1].header("range"), Some(&b"bytes=0-9, -10"[..]));
+        assert_eq!(requests[3].method, "HEAD");
+    }
+
+    #[test]
+    fn if_range() {
+        let server = test_server().serve().unwrap();
+        let range = ("Range", "bytes=0-9");
+        for (if_range, status) in [
+            ("\"1\"", 206),
+            ("\"2\"", 200),
+            ("W/\"1\"", 200),
+            ("nonsense", 200),
+        ] {
+            let resp = send(&server, "GET", &[range, ("If-Range", if_range)]);
+            assert_eq!(resp.status(), status, "{if_range}");
+        }
+    }
+
+    #[test]
+    fn from_file() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/data/lorem.txt");
+        let server = TestServer::from_file(path).unwrap().serve().unwrap();
+        let resp = send(&server, "GET", &[("Range", "bytes=0-9")]);
+        assert_eq!(resp.status(), 206);
+        assert!(resp.etag().is_some());
+        let modified = resp.last_modified().unwrap();
+        let resp = send(
+            &server,
+            "GET",
+            &[
+                ("Range", "bytes=0-9"),
+                ("If-Range", &httpdate::fmt_http_date(modified)),
+            ],
+        );
+        assert_eq!(resp.status(), 206);
+    }
+
+    #[test]
+    fn no_ranges() {
+        let mut server = test_server();
+        server.set_accept_ranges(false);
+        let server = server.serve().unwrap();
+        let resp = send(&server, "GET", &[("Range", "bytes=0-9")]);
+        assert_eq!(resp.status(), 200);
+        assert_eq!(resp.header("Accept-Ranges"), Some(&b"none"[..]));
+        assert_eq!(resp.data().len(), 4057);
+    }
+
+    #[test]
+    fn misbehaviours() {
+        let mut server = test_server();
+        server.misbehave(Misbehaviour::IgnoreRange);
+        let server = server.serve().unwrap();
+        let resp = send(&server, "GET", &[("Range", "bytes=0-9")]);
+        assert_eq!(resp.status(), 200);
+        assert_eq!(resp.header("Accept-Ranges"), Some(&b"bytes"[..]));
+        drop(server);
+
+        let mut server = test_server();
+        server.misbehave(Misbehaviour::OverlappingParts);
+        let server = server.serve().unwrap();
+        let resp = send(&server, "GET", &[("Range", "bytes=0-9,20-29")]);
+        let spans: Vec<_> = resp
+            .parts()
+            .unwrap()
+            .map(|p| p.unwrap().offset_len().unwrap())
+            .collect();
+        assert_eq!(spans, [(0, 10), (20, 10), (0, 30)]);
+        drop(server);
+
+        let mut server = test_server();
+        server.misbehave(Misbehaviour::WrongContentLength);
+        let server = server.serve().unwrap();
+        let resp = send(&server, "GET", &[("Range", "bytes=0-9")]);
+        assert!(resp.parts().unwrap().next().unwrap().is_err());
+    }
+
+    #[cfg(feature = "reqwest")]
+    #[test]
+    fn remote_file() {
+        use crate::client::ClientTransport;
+        use crate::remote::RemoteFile;
+
+        let server = test_server().serve().unwrap();
+        let transport = ClientTransport::new(
+            reqwest::blocking::Client::new(),
+            server.url().parse().unwrap(),
+        );
+        let mut file = RemoteFile::new(transport);
+        file.set_block_size(1000);
+        let mut buf = Vec::default();
+        file.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, read_text());
+        let methods: Vec<_> = server.requests().into_iter().map(|r| r.method).collect();
+        assert_eq!(methods, ["HEAD", "GET", "GET"]);
+    }
+
+    #[cfg(feature = "reqwest")]
+    #[test]
+    fn remote_file_range_ignored() {
+        use crate::client::ClientTransport;
+        use crate::remote::RemoteFile;
+
+        let mut server = test_server();
+        server.misbehave(Misbehaviour::IgnoreRange);
+        let server = server.serve().unwrap();
+        let transport = ClientTransport::new(
+            reqwest::blocking::Client::new(),
+            server.url().parse().unwrap(),
+        );
+        let mut file = RemoteFile::new(transport);
+        file.set_block_size(1000);
+        let mut buf = Vec::default();
+        file.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, read_text());
+        // the first GET returned the whole file, so there is no need for more
+        let methods: Vec<_> = server.requests().into_iter().map(|r| r.method).collect();
+        assert_eq!(methods, ["HEAD", "GET"]);
+    }
+}